@@ -1,3 +1,4 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { GameMode } from "./GameMode";
 
-export type CreateLobbyData = { name: string, max_players: number, };
\ No newline at end of file
+export type CreateLobbyData = { name: string, max_players: number, deck: string | null, seven_o: boolean, jump_in: boolean, mode: GameMode, partner_exchange: boolean, private: boolean, password: string | null, };
\ No newline at end of file
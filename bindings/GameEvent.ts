@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type GameEvent = { "tag": "Started" } | { "tag": "CardsPlayed", "fields": { player: number, count: number, } } | { "tag": "CardsDrawn", "fields": { player: number, count: number, } } | { "tag": "HandsSwapped", "fields": { player: number, target: number, } } | { "tag": "HandsRotated" } | { "tag": "JumpedIn", "fields": { player: number, } } | { "tag": "TeamChanged", "fields": { player: number, team: number, } } | { "tag": "CardGiven", "fields": { player: number, partner: number, } } | { "tag": "RoundOver", "fields": { winner: number, team: number | null, points: number, hand_points: Array<number>, } } | { "tag": "Eliminated", "fields": { player: number, } } | { "tag": "GameOver", "fields": { winner: number, } };
\ No newline at end of file
@@ -1,3 +1,4 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Rules } from "./Rules";
 
-export type LobbyData = { name: string, players: number, max_players: number, id: string, };
\ No newline at end of file
+export type LobbyData = { name: string, players: number, max_players: number, id: string, game_started: boolean, owner: string, locked: boolean, rules: Rules, finished: boolean, };
\ No newline at end of file
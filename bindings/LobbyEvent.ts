@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { LobbyData } from "./LobbyData";
+
+export type LobbyEvent = { "tag": "Created", "fields": LobbyData } | { "tag": "Updated", "fields": LobbyData } | { "tag": "Removed", "fields": { id: string, } } | { "tag": "Resync" };
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { LobbyData } from "./LobbyData";
+
+export type LobbyPage = { lobbies: Array<LobbyData>, next: string | null, };
\ No newline at end of file
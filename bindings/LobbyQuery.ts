@@ -0,0 +1,5 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { GameMode } from "./GameMode";
+import type { LobbySort } from "./LobbySort";
+
+export type LobbyQuery = { open: boolean | null, min_seats: number | null, started: boolean | null, mode: GameMode | null, deck: string | null, seven_o: boolean | null, jump_in: boolean | null, name: string | null, sort: LobbySort, cursor: string | null, limit: number | null, };
\ No newline at end of file
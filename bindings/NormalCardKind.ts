@@ -1,3 +1,3 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 
-export type NormalCardKind = { "tag": "Number", "fields": number } | { "tag": "Reverse" } | { "tag": "PlusTwo" } | { "tag": "Block" };
\ No newline at end of file
+export type NormalCardKind = { "tag": "Number", "fields": number } | { "tag": "Reverse" } | { "tag": "PlusTwo" } | { "tag": "Block" } | { "tag": "SkipAll" } | { "tag": "DiscardAll" };
\ No newline at end of file
@@ -1,4 +1,4 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 import type { User } from "./User";
 
-export type PlayerInfo = { user: User, cardCount: number, };
\ No newline at end of file
+export type PlayerInfo = { user: User, cardCount: number, connected: boolean, latencyMs: number | null, team: number | null, eliminated: boolean, };
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { GameMode } from "./GameMode";
+
+export type QueueData = { players: number, deck: string | null, seven_o: boolean, jump_in: boolean, mode: GameMode, };
\ No newline at end of file
@@ -1,4 +1,4 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 import type { Color } from "./Color";
 
-export type Request = { "tag": "PlaySpecialCard", "fields": [number, Color] } | { "tag": "PlayCards", "fields": Array<number> } | { "tag": "TakeCard" } | { "tag": "SendMessage", "fields": { content: string, } };
\ No newline at end of file
+export type Request = { "tag": "PlaySpecialCard", "fields": [number, Color] } | { "tag": "PlayCards", "fields": Array<number> } | { "tag": "PlayTargetedCard", "fields": { card: number, color: Color, target: number, } } | { "tag": "ChooseSwapTarget", "fields": number } | { "tag": "JumpIn", "fields": { card: number, cards_played: number, } } | { "tag": "GiveToPartner", "fields": number } | { "tag": "TakeCard" } | { "tag": "SendMessage", "fields": { content: string, } } | { "tag": "Hello", "fields": { protocol_version: number, client_name: string, password?: string | null, } };
\ No newline at end of file
@@ -1,5 +1,6 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 import type { ChatMessage } from "./ChatMessage";
 import type { GameState } from "./GameState";
+import type { Welcome } from "./Welcome";
 
-export type Response = { "tag": "ChatMessage", "fields": ChatMessage } | { "tag": "GameState", "fields": GameState };
\ No newline at end of file
+export type Response = { "tag": "ChatMessage", "fields": ChatMessage } | { "tag": "GameState", "fields": GameState } | { "tag": "Welcome", "fields": Welcome } | { "tag": "Error", "fields": string } | { "tag": "ServerShutdown", "fields": { in_seconds: number, } };
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { GameMode } from "./GameMode";
+
+export type Rules = { maxPlayers: number, handSize: number, deck: string, sevenO: boolean, jumpIn: boolean, mode: GameMode, partnerExchange: boolean, };
\ No newline at end of file
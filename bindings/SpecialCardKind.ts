@@ -1,3 +1,3 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 
-export type SpecialCardKind = "PlusFour" | "ChangeColor";
\ No newline at end of file
+export type SpecialCardKind = "PlusFour" | "ChangeColor" | "DrawColor" | "SwapHands";
\ No newline at end of file
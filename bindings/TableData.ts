@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type TableData = { lobby: string | null, players: Array<string>, winner: string | null, abandoned: boolean, };
\ No newline at end of file
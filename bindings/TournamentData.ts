@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { TableData } from "./TableData";
+
+export type TournamentData = { id: string, name: string, table_size: number, started: boolean, finished: boolean, players: Array<string>, rounds: Array<Array<TableData>>, winner: string | null, };
\ No newline at end of file
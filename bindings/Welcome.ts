@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Rules } from "./Rules";
+
+export type Welcome = { protocolVersion: number, supportedVersions: Array<number>, rules: Rules, };
\ No newline at end of file
@@ -0,0 +1,541 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use axum::http::{header::CONTENT_TYPE, HeaderValue, Method};
+use serde::Deserialize;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use tracing::Level;
+
+use crate::{lobby::LobbyLimits, rate_limit::RateLimits, room::RoomSettings, socket::Heartbeat};
+
+/// Read from the working directory when no other config file is given
+const DEFAULT_PATH: &str = "cardgame.toml";
+/// `CARDGAME_LOG__LEVEL=info` sets `log.level`, sections are separated by two underscores
+const ENV_PREFIX: &str = "CARDGAME_";
+
+pub const USAGE: &str = "\
+Usage: server [OPTIONS]
+
+Settings are read from the config file, then CARDGAME_* environment variables, then these flags.
+See cardgame.example.toml for every setting.
+
+Options:
+  --config <PATH>        Config file, defaults to CARDGAME_CONFIG or ./cardgame.toml if it exists
+  --bind <ADDRESS>       Address to listen on, like localhost:8080
+  --log-level <LEVEL>    trace, debug, info, warn or error
+  --log-format <FORMAT>  full, compact, pretty or json
+  --set <KEY=VALUE>      Any other setting, like --set lobby.max_players=6
+  -h, --help             Print this message";
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Address the server listens on, as host:port
+    pub bind: String,
+    pub log: LogConfig,
+    /// Origins allowed to call the API from a browser, none means same origin only
+    pub cors_origins: Vec<String>,
+    /// Only send the session cookie over HTTPS
+    pub secure_cookies: bool,
+    /// Compress the web client's files for browsers that accept it
+    pub compress: bool,
+    /// Seconds players are warned before the server shuts down
+    pub shutdown_grace: u64,
+    /// Seconds between saves of the games in progress, when `paths.snapshot` is set
+    pub snapshot_interval: u64,
+    pub paths: Paths,
+    pub lobby: LobbyLimits,
+    pub heartbeat: HeartbeatConfig,
+    pub room: RoomConfig,
+    pub rate_limits: RateLimits,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bind: "localhost:8080".into(),
+            log: LogConfig::default(),
+            cors_origins: Vec::new(),
+            secure_cookies: false,
+            compress: true,
+            shutdown_grace: 10,
+            snapshot_interval: 30,
+            paths: Paths::default(),
+            lobby: LobbyLimits::default(),
+            heartbeat: HeartbeatConfig::default(),
+            room: RoomConfig::default(),
+            rate_limits: RateLimits::default(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub level: LogLevel,
+    pub format: LogFormat,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(try_from = "String")]
+pub struct LogLevel(pub Level);
+
+impl Default for LogLevel {
+    fn default() -> Self {
+        Self(Level::DEBUG)
+    }
+}
+
+impl TryFrom<String> for LogLevel {
+    type Error = String;
+
+    fn try_from(level: String) -> Result<Self, String> {
+        level.parse().map(Self).map_err(|_| {
+            format!("unknown log level {level:?}, use trace, debug, info, warn or error")
+        })
+    }
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Full,
+    Compact,
+    Pretty,
+    /// One JSON object per line, for log collectors
+    Json,
+}
+
+impl LogConfig {
+    pub fn init(&self) {
+        let builder = tracing_subscriber::fmt().with_max_level(self.level.0);
+        match self.format {
+            LogFormat::Full => builder.init(),
+            LogFormat::Compact => builder.compact().init(),
+            LogFormat::Pretty => builder.pretty().init(),
+            LogFormat::Json => builder.json().init(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Paths {
+    /// Deck files in here can be picked when creating a lobby, next to the built in presets
+    pub decks: PathBuf,
+    /// The built web client, not read when it's embedded in the binary
+    pub web: PathBuf,
+    /// Games in progress are saved here and picked back up on startup. Holds session tokens and
+    /// lobby passwords, so treat it like a secret
+    pub snapshot: Option<PathBuf>,
+}
+
+impl Default for Paths {
+    fn default() -> Self {
+        Self {
+            decks: "decks".into(),
+            web: "web/dist".into(),
+            snapshot: None,
+        }
+    }
+}
+
+/// [`Heartbeat`] in whole seconds
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct HeartbeatConfig {
+    pub interval: u64,
+    pub timeout: u64,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        let heartbeat = Heartbeat::default();
+        Self {
+            interval: heartbeat.interval.as_secs(),
+            timeout: heartbeat.timeout.as_secs(),
+        }
+    }
+}
+
+/// [`RoomSettings`] with the timeouts in whole seconds
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct RoomConfig {
+    pub idle: u64,
+    pub empty: u64,
+    pub card_history: usize,
+    pub reconnect: u64,
+}
+
+impl Default for RoomConfig {
+    fn default() -> Self {
+        let settings = RoomSettings::default();
+        Self {
+            idle: settings.idle.as_secs(),
+            empty: settings.empty.as_secs(),
+            card_history: settings.card_history,
+            reconnect: settings.reconnect.as_secs(),
+        }
+    }
+}
+
+/// Flags given on the command line
+#[derive(Debug, Default, PartialEq)]
+struct Args {
+    config: Option<PathBuf>,
+    /// Dotted keys and their values, like `("log.level", "info")`
+    overrides: Vec<(String, String)>,
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+    const FLAGS: &[&str] = &["--config", "--set", "--bind", "--log-level", "--log-format"];
+    let mut parsed = Args::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag.to_string(), Some(value.into())),
+            _ => (arg, None),
+        };
+        if !FLAGS.contains(&flag.as_str()) {
+            return Err(format!("Unknown argument {flag}, see --help"));
+        }
+        let Some(value) = inline.or_else(|| args.next()) else {
+            return Err(format!("{flag} needs a value"));
+        };
+        match flag.as_str() {
+            "--config" => parsed.config = Some(value.into()),
+            "--set" => {
+                let Some((key, value)) = value.split_once('=') else {
+                    return Err(format!("--set needs KEY=VALUE, got {value:?}"));
+                };
+                parsed
+                    .overrides
+                    .push((key.trim().into(), value.trim().into()));
+            }
+            // The other flags are named after their key
+            flag => parsed.overrides.push((flag[2..].replace('-', "."), value)),
+        }
+    }
+    Ok(parsed)
+}
+
+/// The config file and overrides set in `CARDGAME_*` variables
+fn env_overrides(
+    vars: impl IntoIterator<Item = (String, String)>,
+) -> (Option<PathBuf>, Vec<(String, String)>) {
+    let mut config = None;
+    let mut overrides = Vec::new();
+    for (name, value) in vars {
+        let Some(name) = name.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+        if name == "CONFIG" {
+            config = Some(value.into());
+        } else {
+            overrides.push((name.to_lowercase().replace("__", "."), value));
+        }
+    }
+    (config, overrides)
+}
+
+fn read_table(path: &Path) -> Result<toml::Table, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Can't read config file {}: {e}", path.display()))?;
+    toml::from_str(&text).map_err(|e| format!("Invalid config file {}: {e}", path.display()))
+}
+
+/// Values are parsed as TOML, anything that isn't valid TOML is taken as a string
+fn parse_value(raw: &str) -> toml::Value {
+    toml::from_str::<toml::Table>(&format!("value = {raw}"))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(raw.into()))
+}
+
+fn at_least_one(errors: &mut Vec<String>, key: &str, value: u64) {
+    if value == 0 {
+        errors.push(format!("{key} must be at least 1"));
+    }
+}
+
+/// Sets a dotted key like `rate_limits.login.burst`, making the sections on the way
+fn set(table: &mut toml::Table, key: &str, raw: &str) -> Result<(), String> {
+    let mut parts: Vec<&str> = key.split('.').collect();
+    let last = parts.pop().unwrap();
+    let mut table = table;
+    for part in parts {
+        let section = table
+            .entry(part)
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+        table = section
+            .as_table_mut()
+            .ok_or_else(|| format!("Can't set {key}, {part} isn't a section"))?;
+    }
+    table.insert(last.into(), parse_value(raw));
+    Ok(())
+}
+
+impl Config {
+    /// The defaults, overridden by the config file, then the environment, then the flags
+    pub fn load(
+        args: impl IntoIterator<Item = String>,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Self, String> {
+        let args = parse_args(args)?;
+        let (env_config, env_overrides) = env_overrides(vars);
+        let mut table = match args.config.or(env_config) {
+            Some(path) => read_table(&path)?,
+            None if Path::new(DEFAULT_PATH).is_file() => read_table(Path::new(DEFAULT_PATH))?,
+            None => toml::Table::new(),
+        };
+        for (key, value) in env_overrides.iter().chain(&args.overrides) {
+            set(&mut table, key, value)?;
+        }
+        let config = Config::deserialize(toml::Value::Table(table))
+            .map_err(|e| format!("Invalid config: {e}"))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks the values the types don't, lists every problem at once
+    fn validate(&self) -> Result<(), String> {
+        let mut errors = Vec::new();
+        at_least_one(
+            &mut errors,
+            "lobby.max_name_length",
+            self.lobby.max_name_length as u64,
+        );
+        at_least_one(
+            &mut errors,
+            "lobby.max_password_length",
+            self.lobby.max_password_length as u64,
+        );
+        at_least_one(
+            &mut errors,
+            "lobby.lobbies_per_user",
+            self.lobby.lobbies_per_user as u64,
+        );
+        at_least_one(&mut errors, "heartbeat.interval", self.heartbeat.interval);
+        at_least_one(&mut errors, "room.idle", self.room.idle);
+        at_least_one(&mut errors, "room.empty", self.room.empty);
+        at_least_one(&mut errors, "room.reconnect", self.room.reconnect);
+        at_least_one(&mut errors, "snapshot_interval", self.snapshot_interval);
+        let limits = &self.rate_limits;
+        for (name, limit) in [
+            ("socket", limits.socket),
+            ("login", limits.login),
+            ("create_lobby", limits.create_lobby),
+            ("password", limits.password),
+        ] {
+            at_least_one(
+                &mut errors,
+                &format!("rate_limits.{name}.burst"),
+                limit.burst.into(),
+            );
+            if !(limit.per_second.is_finite() && limit.per_second > 0.0) {
+                errors.push(format!("rate_limits.{name}.per_second must be more than 0"));
+            }
+        }
+
+        let valid_bind = self
+            .bind
+            .rsplit_once(':')
+            .is_some_and(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok());
+        if !valid_bind {
+            errors.push(format!("bind must be host:port, got {:?}", self.bind));
+        }
+        for origin in &self.cors_origins {
+            let valid = (origin.starts_with("http://") || origin.starts_with("https://"))
+                && !origin.ends_with('/')
+                && HeaderValue::from_str(origin).is_ok();
+            if !valid {
+                errors.push(format!(
+                    "cors_origins entries must look like https://example.com, got {origin:?}"
+                ));
+            }
+        }
+        for (key, path) in [
+            ("paths.decks", &self.paths.decks),
+            ("paths.web", &self.paths.web),
+        ] {
+            if path.exists() && !path.is_dir() {
+                errors.push(format!("{key} must be a directory, got {}", path.display()));
+            }
+        }
+        if self.paths.snapshot.as_deref().is_some_and(Path::is_dir) {
+            errors.push("paths.snapshot must be a file, got a directory".into());
+        }
+        if self.lobby.min_players < 2 {
+            errors.push("lobby.min_players must be at least 2".into());
+        }
+        if self.lobby.max_players < self.lobby.min_players {
+            errors.push("lobby.max_players can't be less than lobby.min_players".into());
+        }
+        if self.heartbeat.timeout <= self.heartbeat.interval {
+            errors.push("heartbeat.timeout must be longer than heartbeat.interval".into());
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("Invalid config:\n  {}", errors.join("\n  ")))
+        }
+    }
+
+    pub fn heartbeat(&self) -> Heartbeat {
+        Heartbeat {
+            interval: Duration::from_secs(self.heartbeat.interval),
+            timeout: Duration::from_secs(self.heartbeat.timeout),
+        }
+    }
+
+    pub fn room_settings(&self) -> RoomSettings {
+        RoomSettings {
+            idle: Duration::from_secs(self.room.idle),
+            empty: Duration::from_secs(self.room.empty),
+            card_history: self.room.card_history,
+            reconnect: Duration::from_secs(self.room.reconnect),
+        }
+    }
+
+    /// Lets the configured origins call the API with the session cookie
+    pub fn cors(&self) -> Option<CorsLayer> {
+        if self.cors_origins.is_empty() {
+            return None;
+        }
+        let origins = self.cors_origins.iter().filter_map(|o| o.parse().ok());
+        Some(
+            CorsLayer::new()
+                .allow_origin(AllowOrigin::list(origins))
+                .allow_methods([Method::GET, Method::POST, Method::DELETE])
+                .allow_headers([CONTENT_TYPE])
+                .allow_credentials(true),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_defaults_are_valid() {
+        let config = Config::load(vec![], vec![]).unwrap();
+        assert_eq!(config.bind, "localhost:8080");
+        assert_eq!(config.log.level, LogLevel(Level::DEBUG));
+        assert!(config.cors().is_none());
+    }
+
+    #[test]
+    fn test_example_file_is_valid() {
+        let table = toml::from_str(include_str!("../cardgame.example.toml")).unwrap();
+        let config = Config::deserialize(toml::Value::Table(table)).unwrap();
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn test_flags_override_env_override_file() {
+        let path = std::env::temp_dir().join(format!("{}.toml", Uuid::new_v4()));
+        fs::write(
+            &path,
+            "bind = \"0.0.0.0:80\"\n[lobby]\nmax_players = 6\nmin_players = 3\n",
+        )
+        .unwrap();
+        let config = Config::load(
+            args(&["--set", "lobby.max_players=8", "--log-level=warn"]),
+            vars(&[
+                ("CARDGAME_CONFIG", path.to_str().unwrap()),
+                ("CARDGAME_LOBBY__MAX_PLAYERS", "4"),
+                ("CARDGAME_LOG__FORMAT", "json"),
+                ("CARDGAME_RATE_LIMITS__LOGIN__BURST", "1"),
+                ("CARDGAME_RATE_LIMITS__LOGIN__PER_SECOND", "0.5"),
+                ("OTHER", "ignored"),
+            ]),
+        );
+        fs::remove_file(&path).unwrap();
+        let config = config.unwrap();
+        assert_eq!(config.bind, "0.0.0.0:80");
+        assert_eq!(config.lobby.min_players, 3);
+        assert_eq!(config.lobby.max_players, 8);
+        assert_eq!(config.log.level, LogLevel(Level::WARN));
+        assert_eq!(config.log.format, LogFormat::Json);
+        assert_eq!(config.rate_limits.login.burst, 1);
+        assert_eq!(config.rate_limits.login.per_second, 0.5);
+    }
+
+    #[test]
+    fn test_bad_arguments() {
+        assert!(parse_args(args(&["--bind"])).is_err());
+        assert!(parse_args(args(&["--port", "80"])).is_err());
+        assert!(parse_args(args(&["--set", "bind"])).is_err());
+        assert_eq!(
+            parse_args(args(&["--bind", "[::1]:80", "--config=a.toml"])),
+            Ok(Args {
+                config: Some("a.toml".into()),
+                overrides: vec![("bind".into(), "[::1]:80".into())],
+            })
+        );
+        let missing = Config::load(args(&["--config", "/does/not/exist.toml"]), vec![]);
+        assert!(missing.unwrap_err().contains("/does/not/exist.toml"));
+    }
+
+    #[test]
+    fn test_bad_values() {
+        let err = Config::load(args(&["--set", "lobby.max_player=4"]), vec![]).unwrap_err();
+        assert!(err.contains("max_player"), "{err}");
+        let err = Config::load(args(&["--log-level", "loud"]), vec![]).unwrap_err();
+        assert!(err.contains("loud"), "{err}");
+        let err = Config::load(
+            args(&["--bind", "localhost:1", "--set", "bind.port=80"]),
+            vec![],
+        )
+        .unwrap_err();
+        assert!(err.contains("bind isn't a section"), "{err}");
+    }
+
+    #[test]
+    fn test_validation_lists_every_problem() {
+        let err = Config::load(
+            args(&[
+                "--bind",
+                "localhost",
+                "--set",
+                "cors_origins=[\"https://example.com/\"]",
+                "--set",
+                "lobby.min_players=5",
+                "--set",
+                "lobby.max_players=4",
+                "--set",
+                "heartbeat.timeout=5",
+            ]),
+            vec![],
+        )
+        .unwrap_err();
+        for key in [
+            "bind",
+            "cors_origins",
+            "lobby.max_players",
+            "heartbeat.timeout",
+        ] {
+            assert!(err.contains(key), "{key} missing from {err}");
+        }
+        assert!(!err.contains("min_players must"), "{err}");
+    }
+}
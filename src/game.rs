@@ -1,25 +1,37 @@
 use std::mem;
 
 use rand::seq::SliceRandom;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use ts_rs::TS;
 
-use crate::room::RoomActor;
 mod player;
 pub use player::*;
 mod card;
 pub use card::*;
+mod deck;
+pub use deck::*;
+mod rules;
+pub use rules::*;
+mod engine;
+pub use engine::*;
+#[cfg(test)]
+mod proptests;
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct State {
     pub played_cards: Vec<Card>,
     pub unplayed_cards: Vec<Card>,
     pub turn_direction: TurnDirection,
     skip_next: usize,
     give_next: usize,
+    /// Set by `SkipAll`, the current player keeps the turn
+    skip_all: bool,
+    /// Set by `DrawColor`, the next player draws until they get this color
+    draw_until: Option<Color>,
     pub turn_index: usize,
 }
 
-#[derive(TS, Clone, Copy, Serialize, Debug)]
+#[derive(TS, Clone, Copy, Serialize, Deserialize, Debug)]
 #[ts(export)]
 pub enum TurnDirection {
     Clockwise,
@@ -36,48 +48,22 @@ impl TurnDirection {
 
 impl State {
     /// Pops the top card from `unplayed_cards`
-    /// if `unplayed_cards` is empty, shuffles the played cards back into it
-    pub fn draw_card(&mut self) -> Card {
-        match self.unplayed_cards.pop() {
-            Some(card) => card,
-            None => {
-                let top = self.played_cards.pop().unwrap();
-                mem::swap(&mut self.unplayed_cards, &mut self.played_cards);
-                self.unplayed_cards.shuffle(&mut rand::thread_rng());
-                self.played_cards = vec![top];
-                self.unplayed_cards.pop().unwrap()
-            }
+    /// if `unplayed_cards` is empty, shuffles the played cards except the top one back into it.
+    /// Returns `None` once every card is in someone's hand
+    pub fn draw_card(&mut self) -> Option<Card> {
+        if self.unplayed_cards.is_empty() {
+            let top = self.played_cards.pop()?;
+            mem::swap(&mut self.unplayed_cards, &mut self.played_cards);
+            self.unplayed_cards.iter_mut().for_each(Card::reset_color);
+            self.unplayed_cards.shuffle(&mut rand::thread_rng());
+            self.played_cards = vec![top];
         }
+        self.unplayed_cards.pop()
     }
 
-    pub fn next_turn(&mut self, room: &mut RoomActor) {
-        for _ in 0..=self.skip_next {
-            match self.turn_direction {
-                TurnDirection::Clockwise => {
-                    if self.turn_index == 0 {
-                        self.turn_index = room.players.len();
-                    };
-                    self.turn_index -= 1
-                }
-                TurnDirection::CounterClockwise => {
-                    self.turn_index += 1;
-                    if self.turn_index >= room.players.len() {
-                        self.turn_index = 0;
-                    }
-                }
-            }
-        }
-        if self.give_next > 0 {
-            let Some((_, player)) = room.players.get_index_mut(self.turn_index) else {
-                return;
-            };
-            player.cards.extend(
-                self.unplayed_cards
-                    .drain(self.unplayed_cards.len() - self.give_next..),
-            )
-        }
-        self.skip_next = 0;
-        self.give_next = 0;
+    /// How many cards can be drawn before the piles run out, the top card is never reshuffled
+    pub fn drawable(&self) -> usize {
+        self.unplayed_cards.len() + self.played_cards.len().saturating_sub(1)
     }
     pub fn place_card(&mut self, card: Card) {
         match card.kind {
@@ -85,15 +71,31 @@ impl State {
                 NormalCardKind::Block => self.skip_next += 1,
                 NormalCardKind::Reverse => self.turn_direction = self.turn_direction.flip(),
                 NormalCardKind::PlusTwo => self.give_next += 2,
-                NormalCardKind::Number(_) => {}
+                NormalCardKind::SkipAll => self.skip_all = true,
+                // The player's hand is handled by the game
+                NormalCardKind::Number(_) | NormalCardKind::DiscardAll => {}
             },
             CardKind::Special(k) => match k {
                 SpecialCardKind::PlusFour => self.give_next += 4,
-                SpecialCardKind::ChangeColor => {}
+                SpecialCardKind::DrawColor => self.draw_until = Some(card.color),
+                SpecialCardKind::ChangeColor | SpecialCardKind::SwapHands => {}
             },
         }
         self.played_cards.push(card)
     }
+    /// Puts cards on the played pile under the top card, without applying their effects
+    pub fn discard_under_top(&mut self, cards: Vec<Card>) {
+        let at = self.played_cards.len().saturating_sub(1);
+        self.played_cards.splice(at..at, cards);
+    }
+    /// Whether `card` is an exact duplicate of the top card, wild cards never are
+    pub fn can_jump_in(&self, card: &Card) -> bool {
+        self.played_cards.last().is_some_and(|top| {
+            matches!(card.kind, CardKind::Normal(_))
+                && card.kind == top.kind
+                && card.color == top.color
+        })
+    }
     pub fn can_place(&self, card: &Card) -> bool {
         card.color != Color::None && self.can_play(card)
     }
@@ -122,42 +124,32 @@ impl State {
     }
 }
 
-impl Default for State {
-    fn default() -> Self {
-        let mut unplayed_cards: Vec<Card> = Vec::new();
-        let mut id = 0u8;
-        let mut get_id = || {
-            id += 1;
-            id
-        };
-        for color in COLORS {
-            unplayed_cards.push(Card {
-                color,
-                kind: CardKind::Normal(NormalCardKind::Number(0)),
-                id: get_id(),
-            });
-            for _ in 0..2 {
-                for i in 1..=9 {
-                    unplayed_cards.push(Card::number(i, color, get_id()))
-                }
-                unplayed_cards.push(Card::block(color, get_id()));
-                unplayed_cards.push(Card::reverse(color, get_id()));
-                unplayed_cards.push(Card::plus_two(color, get_id()));
-                unplayed_cards.push(Card::plus_four(get_id()));
-                unplayed_cards.push(Card::change_color(get_id()));
-            }
-        }
+impl State {
+    pub fn new(deck: &DeckSpec) -> Self {
+        Self::from_cards(deck.build())
+    }
+
+    /// A fresh state with every card in the unplayed pile
+    pub fn from_cards(cards: Vec<Card>) -> Self {
         Self {
             played_cards: Default::default(),
-            unplayed_cards,
+            unplayed_cards: cards,
             turn_direction: TurnDirection::Clockwise,
             skip_next: 0,
             turn_index: 0,
             give_next: 0,
+            skip_all: false,
+            draw_until: None,
         }
     }
 }
 
+impl Default for State {
+    fn default() -> Self {
+        Self::new(&DeckSpec::classic())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -172,7 +164,7 @@ mod tests {
             unplayed_cards: vec![],
             ..Default::default()
         };
-        let drawn = state.draw_card();
+        let drawn = state.draw_card().unwrap();
         assert_eq!(state.played_cards, vec![Card::block(Color::Yellow, 0)]);
         assert!(state.unplayed_cards.len() == 1);
         assert!(matches!(drawn.color, Color::Red | Color::Blue))
@@ -189,7 +181,7 @@ mod tests {
             unplayed_cards: vec![Card::block(Color::Green, 0)],
             ..Default::default()
         };
-        let drawn = state.draw_card();
+        let drawn = state.draw_card().unwrap();
         assert_eq!(
             state.played_cards,
             vec![
@@ -201,4 +193,62 @@ mod tests {
         assert!(state.unplayed_cards.is_empty());
         assert_eq!(drawn, Card::block(Color::Green, 0))
     }
+
+    #[test]
+    fn test_draw_card_resets_wild_colors() {
+        let mut state = State {
+            played_cards: vec![
+                Card {
+                    color: Color::Red,
+                    ..Card::plus_four(0)
+                },
+                Card::block(Color::Yellow, 1),
+            ],
+            unplayed_cards: vec![],
+            ..Default::default()
+        };
+        assert_eq!(state.draw_card(), Some(Card::plus_four(0)));
+    }
+
+    #[test]
+    fn test_draw_card_exhausted() {
+        let mut state = State {
+            played_cards: vec![Card::block(Color::Red, 0)],
+            unplayed_cards: vec![],
+            ..Default::default()
+        };
+        assert_eq!(state.draw_card(), None);
+        // The top card stays put
+        assert_eq!(state.played_cards, vec![Card::block(Color::Red, 0)]);
+
+        let mut state = State {
+            played_cards: vec![],
+            unplayed_cards: vec![],
+            ..Default::default()
+        };
+        assert_eq!(state.draw_card(), None);
+    }
+
+    #[test]
+    fn test_can_jump_in() {
+        let state = State {
+            played_cards: vec![Card::number(5, Color::Red, 0)],
+            ..Default::default()
+        };
+        assert!(state.can_jump_in(&Card::number(5, Color::Red, 1)));
+        assert!(!state.can_jump_in(&Card::number(5, Color::Blue, 1)));
+        assert!(!state.can_jump_in(&Card::number(6, Color::Red, 1)));
+
+        let state = State {
+            played_cards: vec![Card {
+                color: Color::Red,
+                ..Card::plus_four(0)
+            }],
+            ..Default::default()
+        };
+        assert!(!state.can_jump_in(&Card {
+            color: Color::Red,
+            ..Card::plus_four(1)
+        }));
+    }
 }
@@ -21,6 +21,10 @@ pub enum NormalCardKind {
     Reverse,
     PlusTwo,
     Block,
+    /// Skips everyone else, the player gets another turn
+    SkipAll,
+    /// The player also discards every other card of this color from their hand
+    DiscardAll,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, TS, Serialize, Deserialize)]
@@ -28,6 +32,17 @@ pub enum NormalCardKind {
 pub enum SpecialCardKind {
     PlusFour,
     ChangeColor,
+    /// The next player draws until they get a card of the chosen color
+    DrawColor,
+    /// The player swaps hands with a player of their choice
+    SwapHands,
+}
+
+impl SpecialCardKind {
+    /// Whether playing the card needs a target player
+    pub fn needs_target(&self) -> bool {
+        matches!(self, SpecialCardKind::SwapHands)
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, TS, Serialize, Deserialize)]
@@ -46,6 +61,24 @@ pub struct Card {
     pub id: u8,
 }
 
+impl Card {
+    /// What the card counts for when it's left in a hand at the end of a round
+    pub fn points(&self) -> u32 {
+        match self.kind {
+            CardKind::Normal(NormalCardKind::Number(n)) => n as u32,
+            CardKind::Normal(_) => 20,
+            CardKind::Special(_) => 50,
+        }
+    }
+
+    /// Forgets the color a wild card was played as, before it goes back into the pile
+    pub fn reset_color(&mut self) {
+        if matches!(self.kind, CardKind::Special(_)) {
+            self.color = Color::None;
+        }
+    }
+}
+
 #[allow(unused)]
 impl Card {
     pub fn number(num: u8, color: Color, id: u8) -> Self {
@@ -76,6 +109,20 @@ impl Card {
             id,
         }
     }
+    pub fn skip_all(color: Color, id: u8) -> Self {
+        Self {
+            color,
+            kind: CardKind::Normal(NormalCardKind::SkipAll),
+            id,
+        }
+    }
+    pub fn discard_all(color: Color, id: u8) -> Self {
+        Self {
+            color,
+            kind: CardKind::Normal(NormalCardKind::DiscardAll),
+            id,
+        }
+    }
     pub fn plus_four(id: u8) -> Self {
         Self {
             color: Color::None,
@@ -90,4 +137,18 @@ impl Card {
             id,
         }
     }
+    pub fn draw_color(id: u8) -> Self {
+        Self {
+            color: Color::None,
+            kind: CardKind::Special(SpecialCardKind::DrawColor),
+            id,
+        }
+    }
+    pub fn swap_hands(id: u8) -> Self {
+        Self {
+            color: Color::None,
+            kind: CardKind::Special(SpecialCardKind::SwapHands),
+            id,
+        }
+    }
 }
@@ -0,0 +1,328 @@
+use std::{fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use super::{Card, CardKind, Color, NormalCardKind, SpecialCardKind, COLORS};
+
+pub const CLASSIC_DECK: &str = "classic";
+pub const SMALL_DECK: &str = "small";
+pub const DOUBLE_DECK: &str = "double";
+pub const PARTY_DECK: &str = "party";
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ColoredEntry {
+    pub kind: NormalCardKind,
+    /// How many of this card every color gets
+    pub count: usize,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct WildEntry {
+    pub kind: SpecialCardKind,
+    /// How many of this card are in the whole deck
+    pub count: usize,
+}
+
+/// Describes which cards a deck is made of
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct DeckSpec {
+    pub colored: Vec<ColoredEntry>,
+    #[serde(default)]
+    pub wild: Vec<WildEntry>,
+}
+
+impl DeckSpec {
+    /// The standard 108 card deck
+    pub fn classic() -> Self {
+        let mut colored = vec![ColoredEntry {
+            kind: NormalCardKind::Number(0),
+            count: 1,
+        }];
+        colored.extend((1..=9).map(|i| ColoredEntry {
+            kind: NormalCardKind::Number(i),
+            count: 2,
+        }));
+        for kind in [
+            NormalCardKind::Block,
+            NormalCardKind::Reverse,
+            NormalCardKind::PlusTwo,
+        ] {
+            colored.push(ColoredEntry { kind, count: 2 });
+        }
+        Self {
+            colored,
+            wild: vec![
+                WildEntry {
+                    kind: SpecialCardKind::ChangeColor,
+                    count: 4,
+                },
+                WildEntry {
+                    kind: SpecialCardKind::PlusFour,
+                    count: 4,
+                },
+            ],
+        }
+    }
+
+    /// A 40 card deck for short games
+    pub fn small() -> Self {
+        let mut colored: Vec<_> = (0..=5)
+            .map(|i| ColoredEntry {
+                kind: NormalCardKind::Number(i),
+                count: 1,
+            })
+            .collect();
+        for kind in [
+            NormalCardKind::Block,
+            NormalCardKind::Reverse,
+            NormalCardKind::PlusTwo,
+        ] {
+            colored.push(ColoredEntry { kind, count: 1 });
+        }
+        Self {
+            colored,
+            wild: vec![
+                WildEntry {
+                    kind: SpecialCardKind::ChangeColor,
+                    count: 2,
+                },
+                WildEntry {
+                    kind: SpecialCardKind::PlusFour,
+                    count: 2,
+                },
+            ],
+        }
+    }
+
+    /// Two classic decks shuffled together, for 10 or more players
+    pub fn double() -> Self {
+        let mut spec = Self::classic();
+        spec.colored.iter_mut().for_each(|e| e.count *= 2);
+        spec.wild.iter_mut().for_each(|e| e.count *= 2);
+        spec
+    }
+
+    /// The classic deck with the extra action cards added
+    pub fn party() -> Self {
+        let mut spec = Self::classic();
+        for kind in [NormalCardKind::SkipAll, NormalCardKind::DiscardAll] {
+            spec.colored.push(ColoredEntry { kind, count: 1 });
+        }
+        for kind in [SpecialCardKind::DrawColor, SpecialCardKind::SwapHands] {
+            spec.wild.push(WildEntry { kind, count: 2 });
+        }
+        spec
+    }
+
+    /// The built in decks and their names
+    pub fn presets() -> Vec<(String, Self)> {
+        vec![
+            (CLASSIC_DECK.into(), Self::classic()),
+            (SMALL_DECK.into(), Self::small()),
+            (DOUBLE_DECK.into(), Self::double()),
+            (PARTY_DECK.into(), Self::party()),
+        ]
+    }
+
+    /// Parses and validates a `.toml` or `.json` deck file
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let spec: Self = match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => toml::from_str(&contents).map_err(|e| e.to_string()),
+            Some("json") => serde_json::from_str(&contents).map_err(|e| e.to_string()),
+            _ => Err("deck files must be .toml or .json".into()),
+        }
+        .map_err(|e| format!("{}: {e}", path.display()))?;
+        spec.validate()
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+        Ok(spec)
+    }
+
+    /// Loads every deck file in `dir`, named after the file without its extension
+    pub fn load_dir(dir: &Path) -> Result<Vec<(String, Self)>, String> {
+        let entries = fs::read_dir(dir).map_err(|e| format!("{}: {e}", dir.display()))?;
+        let mut decks = Vec::new();
+        for entry in entries {
+            let path = entry.map_err(|e| e.to_string())?.path();
+            let Some(name) = path.file_stem().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            decks.push((name.to_owned(), Self::load(&path)?));
+        }
+        Ok(decks)
+    }
+
+    pub fn card_count(&self) -> usize {
+        self.colored.iter().map(|e| e.count).sum::<usize>() * COLORS.len()
+            + self.wild.iter().map(|e| e.count).sum::<usize>()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(e) = self
+            .colored
+            .iter()
+            .find(|e| matches!(e.kind, NormalCardKind::Number(n) if n > 9))
+        {
+            return Err(format!("invalid card {:?}, numbers go from 0 to 9", e.kind));
+        }
+        if !self
+            .colored
+            .iter()
+            .any(|e| e.count > 0 && matches!(e.kind, NormalCardKind::Number(_)))
+        {
+            return Err("the deck needs at least one number card to start the game with".into());
+        }
+        // Card ids are a u8 and start from 1
+        if self.card_count() > u8::MAX as usize {
+            return Err(format!(
+                "the deck has {} cards, at most {} are allowed",
+                self.card_count(),
+                u8::MAX
+            ));
+        }
+        Ok(())
+    }
+
+    /// Creates every card in the deck, unshuffled
+    pub fn build(&self) -> Vec<Card> {
+        let mut cards = Vec::with_capacity(self.card_count());
+        let mut id = 0u8;
+        let mut get_id = || {
+            id += 1;
+            id
+        };
+        for color in COLORS {
+            for entry in &self.colored {
+                for _ in 0..entry.count {
+                    cards.push(Card {
+                        color,
+                        kind: CardKind::Normal(entry.kind),
+                        id: get_id(),
+                    });
+                }
+            }
+        }
+        for entry in &self.wild {
+            for _ in 0..entry.count {
+                cards.push(Card {
+                    color: Color::None,
+                    kind: CardKind::Special(entry.kind),
+                    id: get_id(),
+                });
+            }
+        }
+        cards
+    }
+}
+
+impl Default for DeckSpec {
+    fn default() -> Self {
+        Self::classic()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(cards: &[Card], color: Color, kind: CardKind) -> usize {
+        cards
+            .iter()
+            .filter(|c| c.color == color && c.kind == kind)
+            .count()
+    }
+
+    #[test]
+    fn test_classic_deck() {
+        let cards = DeckSpec::classic().build();
+        assert_eq!(cards.len(), 108);
+        for color in COLORS {
+            let normal = |k| CardKind::Normal(k);
+            assert_eq!(count(&cards, color, normal(NormalCardKind::Number(0))), 1);
+            for i in 1..=9 {
+                assert_eq!(count(&cards, color, normal(NormalCardKind::Number(i))), 2);
+            }
+            assert_eq!(count(&cards, color, normal(NormalCardKind::Block)), 2);
+            assert_eq!(count(&cards, color, normal(NormalCardKind::Reverse)), 2);
+            assert_eq!(count(&cards, color, normal(NormalCardKind::PlusTwo)), 2);
+        }
+        let special = |k| CardKind::Special(k);
+        assert_eq!(
+            count(&cards, Color::None, special(SpecialCardKind::PlusFour)),
+            4
+        );
+        assert_eq!(
+            count(&cards, Color::None, special(SpecialCardKind::ChangeColor)),
+            4
+        );
+    }
+
+    #[test]
+    fn test_classic_has_no_extra_cards() {
+        assert!(!DeckSpec::classic().build().iter().any(|c| matches!(
+            c.kind,
+            CardKind::Normal(NormalCardKind::SkipAll | NormalCardKind::DiscardAll)
+                | CardKind::Special(SpecialCardKind::DrawColor | SpecialCardKind::SwapHands)
+        )));
+    }
+
+    #[test]
+    fn test_presets_are_valid() {
+        for (name, spec) in DeckSpec::presets() {
+            spec.validate().unwrap_or_else(|e| panic!("{name}: {e}"));
+            assert_eq!(spec.build().len(), spec.card_count());
+        }
+        assert_eq!(DeckSpec::small().card_count(), 40);
+        assert_eq!(DeckSpec::double().card_count(), 216);
+        assert_eq!(DeckSpec::party().card_count(), 120);
+    }
+
+    #[test]
+    fn test_unique_ids() {
+        let mut ids: Vec<_> = DeckSpec::double().build().iter().map(|c| c.id).collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), 216);
+    }
+
+    #[test]
+    fn test_parse_toml() {
+        let spec: DeckSpec = toml::from_str(
+            r#"
+            [[colored]]
+            kind = { tag = "Number", fields = 3 }
+            count = 2
+
+            [[wild]]
+            kind = "PlusFour"
+            count = 1
+            "#,
+        )
+        .unwrap();
+        spec.validate().unwrap();
+        assert_eq!(spec.card_count(), 9);
+    }
+
+    #[test]
+    fn test_validate() {
+        let no_numbers = DeckSpec {
+            colored: vec![ColoredEntry {
+                kind: NormalCardKind::Block,
+                count: 2,
+            }],
+            wild: vec![],
+        };
+        assert!(no_numbers.validate().is_err());
+        let bad_number = DeckSpec {
+            colored: vec![ColoredEntry {
+                kind: NormalCardKind::Number(10),
+                count: 1,
+            }],
+            wild: vec![],
+        };
+        assert!(bad_number.validate().is_err());
+        let mut too_big = DeckSpec::double();
+        too_big.colored[0].count = 20;
+        assert!(too_big.validate().is_err());
+    }
+}
@@ -0,0 +1,1304 @@
+use std::fmt;
+
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use super::{
+    rotate_hands, swap_hands, Card, CardKind, Color, DeckSpec, GameMode, NormalCardKind, Player,
+    Rules, State, TurnDirection,
+};
+
+/// Something a player does, players are referred to by their seat index
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action {
+    /// Deals the first round, or the next one once a round is over
+    Start,
+    /// Moves a player to another team before the game starts
+    SetTeam {
+        player: usize,
+        team: usize,
+    },
+    GiveToPartner {
+        player: usize,
+        card: usize,
+    },
+    PlayCards {
+        player: usize,
+        cards: Vec<usize>,
+    },
+    /// Plays a wild card that doesn't need a target
+    PlaySpecialCard {
+        player: usize,
+        card: usize,
+        color: Color,
+    },
+    PlayTargetedCard {
+        player: usize,
+        card: usize,
+        color: Color,
+        target: usize,
+    },
+    TakeCard {
+        player: usize,
+    },
+    /// Ends the turn of a player who's away: draws a card for them even if they could play, or
+    /// swaps with the next player if they were choosing who to swap with
+    Pass {
+        player: usize,
+    },
+    ChooseSwapTarget {
+        player: usize,
+        target: usize,
+    },
+    /// `cards_played` is the count the player saw when jumping in
+    JumpIn {
+        player: usize,
+        card: usize,
+        cards_played: usize,
+    },
+}
+
+/// What an action caused, sent to clients with the next game state
+#[derive(Clone, Debug, PartialEq, TS, Serialize)]
+#[ts(export)]
+#[serde(tag = "tag", content = "fields")]
+pub enum GameEvent {
+    Started,
+    /// Includes cards discarded by `DiscardAll`
+    CardsPlayed {
+        player: usize,
+        count: usize,
+    },
+    CardsDrawn {
+        player: usize,
+        count: usize,
+    },
+    /// The player at `player` swapped hands with the one at `target`
+    HandsSwapped {
+        player: usize,
+        target: usize,
+    },
+    /// Everyone passed their hand on in the turn direction
+    HandsRotated,
+    /// The player at this index played out of turn
+    JumpedIn {
+        player: usize,
+    },
+    TeamChanged {
+        player: usize,
+        team: usize,
+    },
+    /// The player gave their partner a card, which card stays secret
+    CardGiven {
+        player: usize,
+        partner: usize,
+    },
+    /// `winner` went out, or had the fewest points left in hand when the piles ran out.
+    /// `points` is the sum of the opponents' hands, `hand_points` are in seat order
+    RoundOver {
+        winner: usize,
+        team: Option<usize>,
+        points: u32,
+        hand_points: Vec<u32>,
+    },
+    /// Had the most points at the end of the round in elimination mode
+    Eliminated {
+        player: usize,
+    },
+    /// Only `winner` is left in elimination mode
+    GameOver {
+        winner: usize,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameError {
+    NotStarted,
+    AlreadyStarted,
+    UnknownPlayer,
+    NotYourTurn,
+    AwaitingSwapTarget,
+    NotAwaitingSwapTarget,
+    InvalidCards,
+    InvalidColor,
+    InvalidTarget,
+    MustPlayCard,
+    JumpInDisabled,
+    StaleJumpIn,
+    DeckTooSmall,
+    RoundOver,
+    TeamsDisabled,
+    InvalidTeam,
+    UnevenTeams,
+    ExchangeDisabled,
+    AlreadyGaveCard,
+    NotEnoughPlayers,
+    GameOver,
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            GameError::NotStarted => "The game hasn't started yet",
+            GameError::AlreadyStarted => "The game has already started",
+            GameError::UnknownPlayer => "No such player",
+            GameError::NotYourTurn => "It's not your turn",
+            GameError::AwaitingSwapTarget => "Waiting for a player to choose who to swap with",
+            GameError::NotAwaitingSwapTarget => "Nobody is choosing who to swap with",
+            GameError::InvalidCards => "Those cards can't be played",
+            GameError::InvalidColor => "Pick a color for the card",
+            GameError::InvalidTarget => "Invalid target",
+            GameError::MustPlayCard => "You have a card you can play",
+            GameError::JumpInDisabled => "Jumping in isn't allowed in this room",
+            GameError::StaleJumpIn => "Someone played before you",
+            GameError::DeckTooSmall => "Not enough cards in the deck",
+            GameError::RoundOver => "The round is over",
+            GameError::TeamsDisabled => "This room isn't played in teams",
+            GameError::InvalidTeam => "That team doesn't exist or is full",
+            GameError::UnevenTeams => "Every team needs exactly two players",
+            GameError::ExchangeDisabled => "Partners can't exchange cards in this room",
+            GameError::AlreadyGaveCard => "You've already given your partner a card this round",
+            GameError::NotEnoughPlayers => "Elimination needs at least two players",
+            GameError::GameOver => "The game is over",
+        })
+    }
+}
+
+impl std::error::Error for GameError {}
+
+/// The rules of the game without any I/O, the room feeds actions in and broadcasts the result
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Game {
+    pub state: State,
+    /// Hands in seat order
+    pub players: Vec<Player>,
+    pub rules: Rules,
+    pub started: bool,
+    /// Set once someone went out or the piles ran out, until the next round is dealt
+    pub round_over: bool,
+    /// Set once a single player is left in elimination mode
+    pub game_over: bool,
+    /// How many cards have been played in total, including discarded ones
+    pub cards_played: usize,
+    /// Set while the current player has to pick who to swap hands with, the turn is paused
+    pub awaiting_swap_target: bool,
+}
+
+impl Game {
+    /// A game with a shuffled deck and no players
+    pub fn new(rules: Rules, deck: &DeckSpec) -> Self {
+        let mut state = State::new(deck);
+        state.unplayed_cards.shuffle(&mut rand::thread_rng());
+        Self {
+            state,
+            players: Vec::new(),
+            rules,
+            started: false,
+            round_over: false,
+            game_over: false,
+            cards_played: 0,
+            awaiting_swap_target: false,
+        }
+    }
+
+    /// Adds an empty hand and returns its seat
+    pub fn add_player(&mut self) -> Result<usize, GameError> {
+        if self.started {
+            return Err(GameError::AlreadyStarted);
+        }
+        let team = self.rules.teams().then(|| self.smallest_team());
+        self.players.push(Player {
+            team,
+            ..Default::default()
+        });
+        Ok(self.players.len() - 1)
+    }
+
+    fn team_size(&self, team: usize) -> usize {
+        self.players.iter().filter(|p| p.team == Some(team)).count()
+    }
+
+    fn smallest_team(&self) -> usize {
+        (0..self.rules.team_count())
+            .min_by_key(|&t| self.team_size(t))
+            .unwrap_or(0)
+    }
+
+    /// The other player on `player`'s team
+    pub fn partner(&self, player: usize) -> Option<usize> {
+        let team = self.players.get(player)?.team?;
+        (0..self.players.len()).find(|&i| i != player && self.players[i].team == Some(team))
+    }
+
+    /// Whether `a` and `b` win together
+    fn same_side(&self, a: usize, b: usize) -> bool {
+        a == b || (self.players[a].team.is_some() && self.players[a].team == self.players[b].team)
+    }
+
+    /// Removes a seat before the game starts, later seats move down by one
+    pub fn remove_player(&mut self, player: usize) -> Result<(), GameError> {
+        if self.started {
+            return Err(GameError::AlreadyStarted);
+        }
+        if player >= self.players.len() {
+            return Err(GameError::UnknownPlayer);
+        }
+        let removed = self.players.remove(player);
+        self.state.unplayed_cards.extend(removed.cards);
+        Ok(())
+    }
+
+    pub fn apply(&mut self, action: Action) -> Result<Vec<GameEvent>, GameError> {
+        match action {
+            Action::Start => self.start(),
+            Action::SetTeam { player, team } => self.set_team(player, team),
+            Action::GiveToPartner { player, card } => self.give_to_partner(player, card),
+            Action::PlayCards { player, cards } => self.play_cards(player, cards),
+            Action::PlaySpecialCard {
+                player,
+                card,
+                color,
+            } => self.play_special_card(player, card, color),
+            Action::PlayTargetedCard {
+                player,
+                card,
+                color,
+                target,
+            } => self.play_targeted_card(player, card, color, target),
+            Action::TakeCard { player } => self.take_card(player),
+            Action::Pass { player } => self.pass(player),
+            Action::ChooseSwapTarget { player, target } => self.choose_swap_target(player, target),
+            Action::JumpIn {
+                player,
+                card,
+                cards_played,
+            } => self.jump_in(player, card, cards_played),
+        }
+    }
+
+    fn start(&mut self) -> Result<Vec<GameEvent>, GameError> {
+        if self.game_over {
+            return Err(GameError::GameOver);
+        }
+        if self.started && !self.round_over {
+            return Err(GameError::AlreadyStarted);
+        }
+        let active = self.players.iter().filter(|p| !p.eliminated).count();
+        if active == 0 {
+            return Err(GameError::UnknownPlayer);
+        }
+        if self.rules.mode == GameMode::Elimination && active < 2 {
+            return Err(GameError::NotEnoughPlayers);
+        }
+        if self.rules.teams() {
+            let sizes: Vec<usize> = (0..self.rules.team_count())
+                .map(|t| self.team_size(t))
+                .filter(|&n| n > 0)
+                .collect();
+            if sizes.len() < 2 || sizes.iter().any(|&n| n != 2) {
+                return Err(GameError::UnevenTeams);
+            }
+        }
+        self.collect_cards();
+        // Everyone's hand plus the starting card
+        if active * self.rules.hand_size >= self.state.unplayed_cards.len() {
+            return Err(GameError::DeckTooSmall);
+        }
+        let unplayed = &mut self.state.unplayed_cards;
+        for p in self.players.iter_mut().filter(|p| !p.eliminated) {
+            p.cards = unplayed.split_off(unplayed.len() - self.rules.hand_size);
+        }
+        let Some(index) = unplayed
+            .iter()
+            .rposition(|card| matches!(card.kind, CardKind::Normal(NormalCardKind::Number(_))))
+        else {
+            // Put the hands back so the game can be started again
+            self.collect_cards();
+            return Err(GameError::DeckTooSmall);
+        };
+        let top_card = unplayed.remove(index);
+        self.state.place_card(top_card);
+        self.state.turn_index = self.players.iter().position(|p| !p.eliminated).unwrap_or(0);
+        for p in &mut self.players {
+            p.gave_card = false;
+        }
+        self.started = true;
+        self.round_over = false;
+        self.awaiting_swap_target = false;
+        self.cards_played += 1;
+        Ok(vec![GameEvent::Started])
+    }
+
+    /// Shuffles every card back into a fresh unplayed pile
+    fn collect_cards(&mut self) {
+        let mut cards = std::mem::take(&mut self.state.unplayed_cards);
+        cards.append(&mut self.state.played_cards);
+        for p in &mut self.players {
+            cards.append(&mut p.cards);
+        }
+        cards.iter_mut().for_each(Card::reset_color);
+        cards.shuffle(&mut rand::thread_rng());
+        self.state = State::from_cards(cards);
+    }
+
+    fn set_team(&mut self, player: usize, team: usize) -> Result<Vec<GameEvent>, GameError> {
+        if !self.rules.teams() {
+            return Err(GameError::TeamsDisabled);
+        }
+        if self.started {
+            return Err(GameError::AlreadyStarted);
+        }
+        let current = self
+            .players
+            .get(player)
+            .ok_or(GameError::UnknownPlayer)?
+            .team;
+        if team >= self.rules.team_count() || (current != Some(team) && self.team_size(team) >= 2) {
+            return Err(GameError::InvalidTeam);
+        }
+        self.players[player].team = Some(team);
+        Ok(vec![GameEvent::TeamChanged { player, team }])
+    }
+
+    /// Passes a card to the player's partner, allowed once per round at any time
+    fn give_to_partner(&mut self, player: usize, card: usize) -> Result<Vec<GameEvent>, GameError> {
+        if !self.rules.partner_exchange {
+            return Err(GameError::ExchangeDisabled);
+        }
+        self.check_playing()?;
+        let partner = self.partner(player).ok_or(GameError::UnknownPlayer)?;
+        let hand = &mut self.players[player];
+        if hand.gave_card {
+            return Err(GameError::AlreadyGaveCard);
+        }
+        // Giving away the last card doesn't count as going out
+        if card >= hand.cards.len() || hand.cards.len() == 1 {
+            return Err(GameError::InvalidCards);
+        }
+        let card = hand.cards.remove(card);
+        hand.gave_card = true;
+        self.players[partner].cards.push(card);
+        Ok(vec![GameEvent::CardGiven { player, partner }])
+    }
+
+    fn check_playing(&self) -> Result<(), GameError> {
+        if !self.started {
+            return Err(GameError::NotStarted);
+        }
+        if self.round_over {
+            return Err(GameError::RoundOver);
+        }
+        Ok(())
+    }
+
+    fn check_turn(&self, player: usize) -> Result<(), GameError> {
+        self.check_playing()?;
+        if player >= self.players.len() {
+            return Err(GameError::UnknownPlayer);
+        }
+        if self.awaiting_swap_target {
+            return Err(GameError::AwaitingSwapTarget);
+        }
+        if player != self.state.turn_index {
+            return Err(GameError::NotYourTurn);
+        }
+        Ok(())
+    }
+
+    fn check_target(&self, player: usize, target: usize) -> Result<(), GameError> {
+        if target == player || self.players.get(target).is_none_or(|p| p.eliminated) {
+            return Err(GameError::InvalidTarget);
+        }
+        Ok(())
+    }
+
+    fn take_card(&mut self, player: usize) -> Result<Vec<GameEvent>, GameError> {
+        self.check_turn(player)?;
+        if self.players[player].can_play_card(&self.state) {
+            return Err(GameError::MustPlayCard);
+        }
+        let mut events = Vec::new();
+        self.draw(player, 1, None, &mut events);
+        if !self.round_over {
+            self.next_turn(&mut events);
+        }
+        Ok(events)
+    }
+
+    fn pass(&mut self, player: usize) -> Result<Vec<GameEvent>, GameError> {
+        if self.awaiting_swap_target {
+            let count = self.players.len();
+            let target = (1..count)
+                .map(|i| (player + i) % count)
+                .find(|&t| self.check_target(player, t).is_ok())
+                .ok_or(GameError::InvalidTarget)?;
+            return self.choose_swap_target(player, target);
+        }
+        self.check_turn(player)?;
+        let mut events = Vec::new();
+        self.draw(player, 1, None, &mut events);
+        if !self.round_over {
+            self.next_turn(&mut events);
+        }
+        Ok(events)
+    }
+
+    fn play_special_card(
+        &mut self,
+        player: usize,
+        card_index: usize,
+        color: Color,
+    ) -> Result<Vec<GameEvent>, GameError> {
+        self.check_turn(player)?;
+        if color == Color::None {
+            return Err(GameError::InvalidColor);
+        }
+        let hand = &mut self.players[player];
+        if !hand
+            .cards
+            .get(card_index)
+            .is_some_and(|c| matches!(c.kind, CardKind::Special(k) if !k.needs_target()))
+        {
+            return Err(GameError::InvalidCards);
+        }
+        let mut card = hand.cards.remove(card_index);
+        card.color = color;
+        self.state.place_card(card);
+        self.cards_played += 1;
+        let mut events = vec![GameEvent::CardsPlayed { player, count: 1 }];
+        self.finish_turn(&mut events);
+        Ok(events)
+    }
+
+    fn play_targeted_card(
+        &mut self,
+        player: usize,
+        card_index: usize,
+        color: Color,
+        target: usize,
+    ) -> Result<Vec<GameEvent>, GameError> {
+        self.check_turn(player)?;
+        self.check_target(player, target)?;
+        if color == Color::None {
+            return Err(GameError::InvalidColor);
+        }
+        let hand = &mut self.players[player];
+        if !hand
+            .cards
+            .get(card_index)
+            .is_some_and(|c| matches!(c.kind, CardKind::Special(k) if k.needs_target()))
+        {
+            return Err(GameError::InvalidCards);
+        }
+        let mut card = hand.cards.remove(card_index);
+        card.color = color;
+        // SwapHands is the only targeted card
+        swap_hands(&mut self.players, player, target);
+        self.state.place_card(card);
+        self.cards_played += 1;
+        let mut events = vec![
+            GameEvent::CardsPlayed { player, count: 1 },
+            GameEvent::HandsSwapped { player, target },
+        ];
+        self.finish_turn(&mut events);
+        Ok(events)
+    }
+
+    fn play_cards(
+        &mut self,
+        player: usize,
+        card_indeces: Vec<usize>,
+    ) -> Result<Vec<GameEvent>, GameError> {
+        self.check_turn(player)?;
+        let hand = &mut self.players[player];
+        if !hand.can_play_consecutive_cards(&self.state, &card_indeces) {
+            return Err(GameError::InvalidCards);
+        }
+        // The player can actually play all the cards in card_indeces
+        let cards = card_indeces
+            .iter()
+            .map(|i| hand.cards[*i].clone())
+            .collect();
+        hand.cards = std::mem::take(&mut hand.cards)
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, c)| (!card_indeces.contains(&i)).then_some(c))
+            .collect();
+        Ok(self.place_cards(player, cards))
+    }
+
+    /// Plays an exact duplicate of the top card out of turn, the turn continues from the player.
+    /// When several players jump in at once only the first one to reach the game gets to play
+    fn jump_in(
+        &mut self,
+        player: usize,
+        card_index: usize,
+        cards_played: usize,
+    ) -> Result<Vec<GameEvent>, GameError> {
+        if !self.rules.jump_in {
+            return Err(GameError::JumpInDisabled);
+        }
+        self.check_playing()?;
+        if self.awaiting_swap_target {
+            return Err(GameError::AwaitingSwapTarget);
+        }
+        if cards_played != self.cards_played {
+            return Err(GameError::StaleJumpIn);
+        }
+        let hand = self
+            .players
+            .get_mut(player)
+            .ok_or(GameError::UnknownPlayer)?;
+        if !hand
+            .cards
+            .get(card_index)
+            .is_some_and(|c| self.state.can_jump_in(c))
+        {
+            return Err(GameError::InvalidCards);
+        }
+        let card = hand.cards.remove(card_index);
+        self.state.turn_index = player;
+        let mut events = vec![GameEvent::JumpedIn { player }];
+        events.extend(self.place_cards(player, vec![card]));
+        Ok(events)
+    }
+
+    /// Places cards already taken out of the hand of `player` and moves the turn on. The cards
+    /// have to be consecutive
+    fn place_cards(&mut self, player: usize, cards: Vec<Card>) -> Vec<GameEvent> {
+        // Consecutive cards are all of the same kind
+        let played_kind = cards[0].kind;
+        let count = cards.len();
+        let mut cards_played = count;
+        let mut discard_colors = Vec::new();
+        for card in cards {
+            if card.kind == CardKind::Normal(NormalCardKind::DiscardAll) {
+                discard_colors.push(card.color);
+            }
+            self.state.place_card(card)
+        }
+        for color in discard_colors {
+            let discarded = self.players[player].take_color(color);
+            cards_played += discarded.len();
+            self.state.discard_under_top(discarded);
+        }
+        self.cards_played += cards_played;
+        let mut events = vec![GameEvent::CardsPlayed {
+            player,
+            count: cards_played,
+        }];
+        if self.went_out(&mut events) {
+            return events;
+        }
+
+        if self.rules.seven_o {
+            match played_kind {
+                CardKind::Normal(NormalCardKind::Number(0)) => {
+                    for _ in 0..count {
+                        rotate_hands(&mut self.players, self.state.turn_direction);
+                    }
+                    events.push(GameEvent::HandsRotated);
+                }
+                CardKind::Normal(NormalCardKind::Number(7)) => {
+                    // The turn continues once the player has chosen who to swap with
+                    self.awaiting_swap_target = true;
+                    return events;
+                }
+                _ => {}
+            }
+        }
+        self.next_turn(&mut events);
+        events
+    }
+
+    /// Ends the round if someone has run out of cards, otherwise moves the turn on
+    fn finish_turn(&mut self, events: &mut Vec<GameEvent>) {
+        if !self.went_out(events) {
+            self.next_turn(events);
+        }
+    }
+
+    /// Ends the round if someone has run out of cards
+    fn went_out(&mut self, events: &mut Vec<GameEvent>) -> bool {
+        match self
+            .players
+            .iter()
+            .position(|p| !p.eliminated && p.cards.is_empty())
+        {
+            Some(winner) => {
+                self.end_round(Some(winner), events);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn choose_swap_target(
+        &mut self,
+        player: usize,
+        target: usize,
+    ) -> Result<Vec<GameEvent>, GameError> {
+        self.check_playing()?;
+        if !self.awaiting_swap_target {
+            return Err(GameError::NotAwaitingSwapTarget);
+        }
+        if player != self.state.turn_index {
+            return Err(GameError::NotYourTurn);
+        }
+        self.check_target(player, target)?;
+        swap_hands(&mut self.players, player, target);
+        self.awaiting_swap_target = false;
+        let mut events = vec![GameEvent::HandsSwapped { player, target }];
+        self.finish_turn(&mut events);
+        Ok(events)
+    }
+
+    /// Moves the turn on and makes the next player draw whatever the last card gave them
+    fn next_turn(&mut self, events: &mut Vec<GameEvent>) {
+        let state = &mut self.state;
+        let steps = if state.skip_all {
+            0
+        } else {
+            state.skip_next + 1
+        };
+        for _ in 0..steps {
+            // Eliminated players are passed over, there's always someone left
+            loop {
+                match state.turn_direction {
+                    TurnDirection::Clockwise => {
+                        if state.turn_index == 0 {
+                            state.turn_index = self.players.len();
+                        };
+                        state.turn_index -= 1
+                    }
+                    TurnDirection::CounterClockwise => {
+                        state.turn_index += 1;
+                        if state.turn_index >= self.players.len() {
+                            state.turn_index = 0;
+                        }
+                    }
+                }
+                if !self.players[state.turn_index].eliminated {
+                    break;
+                }
+            }
+        }
+        state.skip_next = 0;
+        state.skip_all = false;
+        let player = state.turn_index;
+        let give_next = std::mem::take(&mut state.give_next);
+        let draw_until = state.draw_until.take();
+        if give_next > 0 {
+            self.draw(player, give_next, None, events);
+        }
+        if let Some(color) = draw_until.filter(|_| !self.round_over) {
+            self.draw(player, usize::MAX, Some(color), events);
+        }
+    }
+
+    /// Every card a player gets goes through here. Draws `count` cards or until one of the
+    /// `until` color, the round ends if the piles run out first
+    fn draw(
+        &mut self,
+        player: usize,
+        count: usize,
+        until: Option<Color>,
+        events: &mut Vec<GameEvent>,
+    ) {
+        let mut drawn = 0;
+        let mut exhausted = false;
+        while drawn < count {
+            let Some(card) = self.state.draw_card() else {
+                exhausted = true;
+                break;
+            };
+            let found = until == Some(card.color);
+            self.players[player].cards.push(card);
+            drawn += 1;
+            if found {
+                break;
+            }
+        }
+        if drawn > 0 {
+            events.push(GameEvent::CardsDrawn {
+                player,
+                count: drawn,
+            });
+        }
+        if exhausted {
+            self.end_round(None, events);
+        }
+    }
+
+    /// Scores the round for `out`, who went out. Without one the side with the fewest points
+    /// left in hand wins
+    fn end_round(&mut self, out: Option<usize>, events: &mut Vec<GameEvent>) {
+        let hand_points: Vec<u32> = self
+            .players
+            .iter()
+            .map(|p| p.cards.iter().map(Card::points).sum())
+            .collect();
+        let side_points = |winner: usize, same: bool| -> u32 {
+            (0..hand_points.len())
+                .filter(|&i| self.same_side(winner, i) == same)
+                .map(|i| hand_points[i])
+                .sum()
+        };
+        let active: Vec<usize> = (0..self.players.len())
+            .filter(|&i| !self.players[i].eliminated)
+            .collect();
+        let winner = out
+            .or_else(|| active.iter().copied().min_by_key(|&i| side_points(i, true)))
+            .unwrap_or(0);
+        let points = side_points(winner, false);
+        // The first player with the most points is knocked out
+        let loser = active.iter().copied().rev().max_by_key(|&i| hand_points[i]);
+        self.round_over = true;
+        events.push(GameEvent::RoundOver {
+            winner,
+            team: self.players.get(winner).and_then(|p| p.team),
+            points,
+            hand_points,
+        });
+        if self.rules.mode != GameMode::Elimination {
+            return;
+        }
+        if let Some(loser) = loser {
+            let player = &mut self.players[loser];
+            player.eliminated = true;
+            self.state.unplayed_cards.append(&mut player.cards);
+            events.push(GameEvent::Eliminated { player: loser });
+        }
+        let mut left = self
+            .players
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| !p.eliminated);
+        if let (Some((winner, _)), None) = (left.next(), left.next()) {
+            self.game_over = true;
+            events.push(GameEvent::GameOver { winner });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A started game where player 0 has the turn, `top` is on the played pile
+    fn create_game(hands: Vec<Vec<Card>>, top: Card) -> Game {
+        let mut game = Game::new(Rules::default(), &DeckSpec::classic());
+        game.players = hands
+            .into_iter()
+            .map(|cards| Player {
+                cards,
+                ..Default::default()
+            })
+            .collect();
+        game.state.played_cards = vec![top];
+        game.started = true;
+        game.cards_played = 1;
+        game
+    }
+
+    fn red(n: u8) -> Card {
+        Card::number(n, Color::Red, 0)
+    }
+
+    fn play(player: usize, cards: Vec<usize>) -> Action {
+        Action::PlayCards { player, cards }
+    }
+
+    #[test]
+    fn test_start_deals_hands() {
+        let mut game = Game::new(Rules::default(), &DeckSpec::classic());
+        for _ in 0..3 {
+            game.add_player().unwrap();
+        }
+        assert_eq!(game.apply(Action::Start), Ok(vec![GameEvent::Started]));
+        assert!(game.players.iter().all(|p| p.cards.len() == 7));
+        assert!(matches!(
+            game.state.played_cards[..],
+            [Card {
+                kind: CardKind::Normal(NormalCardKind::Number(_)),
+                ..
+            }]
+        ));
+        assert_eq!(game.state.unplayed_cards.len(), 108 - 3 * 7 - 1);
+        assert_eq!(game.apply(Action::Start), Err(GameError::AlreadyStarted));
+        assert_eq!(game.add_player(), Err(GameError::AlreadyStarted));
+    }
+
+    #[test]
+    fn test_start_deck_too_small() {
+        let mut game = Game::new(Rules::default(), &DeckSpec::small());
+        for _ in 0..6 {
+            game.add_player().unwrap();
+        }
+        assert_eq!(game.apply(Action::Start), Err(GameError::DeckTooSmall));
+        assert!(!game.started);
+    }
+
+    #[test]
+    fn test_turn_order() {
+        let mut game = create_game(
+            vec![vec![red(1), red(4)], vec![red(2)], vec![red(3)]],
+            red(0),
+        );
+        assert_eq!(game.apply(play(1, vec![0])), Err(GameError::NotYourTurn));
+        assert_eq!(
+            game.apply(play(0, vec![0])),
+            Ok(vec![GameEvent::CardsPlayed {
+                player: 0,
+                count: 1
+            }])
+        );
+        // Clockwise goes towards lower indices
+        assert_eq!(game.state.turn_index, 2);
+        assert_eq!(game.cards_played, 2);
+        assert_eq!(game.players[0].cards, vec![red(4)]);
+    }
+
+    #[test]
+    fn test_reverse_and_block() {
+        let hands = vec![
+            vec![Card::reverse(Color::Red, 0), red(1)],
+            vec![Card::block(Color::Red, 0), red(2)],
+            vec![red(3)],
+        ];
+        let mut game = create_game(hands, red(0));
+        game.apply(play(0, vec![0])).unwrap();
+        assert_eq!(game.state.turn_index, 1);
+        game.apply(play(1, vec![0])).unwrap();
+        // Player 2 is skipped
+        assert_eq!(game.state.turn_index, 0);
+    }
+
+    #[test]
+    fn test_plus_two_gives_cards() {
+        let hands = vec![vec![Card::plus_two(Color::Red, 0), red(1)], vec![red(2)]];
+        let mut game = create_game(hands, red(0));
+        let events = game.apply(play(0, vec![0])).unwrap();
+        assert_eq!(
+            events[1],
+            GameEvent::CardsDrawn {
+                player: 1,
+                count: 2
+            }
+        );
+        assert_eq!(game.players[1].cards.len(), 3);
+    }
+
+    #[test]
+    fn test_take_card() {
+        let hands = vec![vec![red(1), red(3)], vec![Card::number(2, Color::Blue, 0)]];
+        let mut game = create_game(hands, red(0));
+        assert_eq!(
+            game.apply(Action::TakeCard { player: 0 }),
+            Err(GameError::MustPlayCard)
+        );
+        game.apply(play(0, vec![0])).unwrap();
+        assert_eq!(
+            game.apply(Action::TakeCard { player: 1 }),
+            Ok(vec![GameEvent::CardsDrawn {
+                player: 1,
+                count: 1
+            }])
+        );
+        assert_eq!(game.players[1].cards.len(), 2);
+        assert_eq!(game.state.turn_index, 0);
+    }
+
+    #[test]
+    fn test_special_card_needs_color() {
+        let mut game = create_game(
+            vec![vec![Card::change_color(0), red(1)], vec![red(2)]],
+            red(0),
+        );
+        let action = |color| Action::PlaySpecialCard {
+            player: 0,
+            card: 0,
+            color,
+        };
+        assert_eq!(
+            game.apply(action(Color::None)),
+            Err(GameError::InvalidColor)
+        );
+        game.apply(action(Color::Blue)).unwrap();
+        assert_eq!(game.state.played_cards.last().unwrap().color, Color::Blue);
+    }
+
+    #[test]
+    fn test_swap_hands_card() {
+        let hands = vec![
+            vec![Card::swap_hands(0), red(1)],
+            vec![red(2)],
+            vec![red(3)],
+        ];
+        let mut game = create_game(hands, red(0));
+        let action = |target| Action::PlayTargetedCard {
+            player: 0,
+            card: 0,
+            color: Color::Green,
+            target,
+        };
+        assert_eq!(game.apply(action(0)), Err(GameError::InvalidTarget));
+        assert_eq!(game.apply(action(3)), Err(GameError::InvalidTarget));
+        game.apply(action(1)).unwrap();
+        assert_eq!(game.players[0].cards, vec![red(2)]);
+        assert_eq!(game.players[1].cards, vec![red(1)]);
+    }
+
+    #[test]
+    fn test_pass_draws_even_with_a_playable_card() {
+        let hands = vec![vec![red(1)], vec![red(2)], vec![red(3)]];
+        let mut game = create_game(hands, red(0));
+        assert_eq!(
+            game.apply(Action::Pass { player: 1 }),
+            Err(GameError::NotYourTurn)
+        );
+        assert_eq!(
+            game.apply(Action::Pass { player: 0 }),
+            Ok(vec![GameEvent::CardsDrawn {
+                player: 0,
+                count: 1
+            }])
+        );
+        assert_eq!(game.players[0].cards.len(), 2);
+        assert_eq!(game.state.turn_index, 2);
+    }
+
+    #[test]
+    fn test_pass_picks_a_swap_target() {
+        let mut game = create_game(
+            vec![vec![red(7), red(1)], vec![red(2)], vec![red(3)]],
+            red(0),
+        );
+        game.rules.seven_o = true;
+        game.apply(play(0, vec![0])).unwrap();
+        assert_eq!(
+            game.apply(Action::Pass { player: 0 }),
+            Ok(vec![GameEvent::HandsSwapped {
+                player: 0,
+                target: 1
+            }])
+        );
+        assert_eq!(game.state.turn_index, 2);
+    }
+
+    #[test]
+    fn test_seven_pauses_for_target() {
+        let mut game = create_game(
+            vec![vec![red(7), red(1)], vec![red(2)], vec![red(3)]],
+            red(0),
+        );
+        game.rules.seven_o = true;
+        game.apply(play(0, vec![0])).unwrap();
+        assert!(game.awaiting_swap_target);
+        assert_eq!(game.state.turn_index, 0);
+        assert_eq!(
+            game.apply(play(0, vec![0])),
+            Err(GameError::AwaitingSwapTarget)
+        );
+        assert_eq!(
+            game.apply(Action::ChooseSwapTarget {
+                player: 0,
+                target: 1
+            }),
+            Ok(vec![GameEvent::HandsSwapped {
+                player: 0,
+                target: 1
+            }])
+        );
+        assert!(!game.awaiting_swap_target);
+        assert_eq!(game.players[0].cards, vec![red(2)]);
+        assert_eq!(game.state.turn_index, 2);
+    }
+
+    #[test]
+    fn test_zero_rotates_hands() {
+        let mut game = create_game(
+            vec![vec![red(0), red(1)], vec![red(2)], vec![red(3)]],
+            red(5),
+        );
+        game.rules.seven_o = true;
+        let events = game.apply(play(0, vec![0])).unwrap();
+        assert!(events.contains(&GameEvent::HandsRotated));
+        assert_eq!(game.players[2].cards, vec![red(1)]);
+    }
+
+    #[test]
+    fn test_jump_in() {
+        let hands = vec![vec![red(1)], vec![red(2)], vec![red(5), red(6)]];
+        let mut game = create_game(hands, red(5));
+        let action = |cards_played| Action::JumpIn {
+            player: 2,
+            card: 0,
+            cards_played,
+        };
+        assert_eq!(game.apply(action(1)), Err(GameError::JumpInDisabled));
+        game.rules.jump_in = true;
+        assert_eq!(game.apply(action(0)), Err(GameError::StaleJumpIn));
+        let events = game.apply(action(1)).unwrap();
+        assert_eq!(events[0], GameEvent::JumpedIn { player: 2 });
+        // The turn continues from the player who jumped in
+        assert_eq!(game.state.turn_index, 1);
+    }
+
+    #[test]
+    fn test_piles_running_out_ends_round() {
+        let hands = vec![
+            vec![Card::plus_two(Color::Red, 0), Card::plus_four(1)],
+            vec![red(9), Card::block(Color::Blue, 2)],
+        ];
+        let mut game = create_game(hands, red(0));
+        game.state.unplayed_cards.clear();
+        let events = game.apply(play(0, vec![0])).unwrap();
+        assert_eq!(
+            events[1..],
+            [
+                // Only the red 0 under the +2 gets reshuffled back
+                GameEvent::CardsDrawn {
+                    player: 1,
+                    count: 1
+                },
+                GameEvent::RoundOver {
+                    winner: 1,
+                    team: None,
+                    points: 50,
+                    hand_points: vec![50, 29]
+                }
+            ]
+        );
+        assert!(game.round_over);
+        assert_eq!(
+            game.apply(Action::TakeCard { player: 1 }),
+            Err(GameError::RoundOver)
+        );
+    }
+
+    #[test]
+    fn test_going_out_ends_round() {
+        let hands = vec![vec![red(1)], vec![red(2), Card::plus_four(0)], vec![red(3)]];
+        let mut game = create_game(hands, red(0));
+        let events = game.apply(play(0, vec![0])).unwrap();
+        assert_eq!(
+            events.last(),
+            Some(&GameEvent::RoundOver {
+                winner: 0,
+                team: None,
+                points: 55,
+                hand_points: vec![0, 52, 3]
+            })
+        );
+        // The turn doesn't move on
+        assert_eq!(game.state.turn_index, 0);
+    }
+
+    fn team_game(players: usize) -> Game {
+        let rules = Rules {
+            max_players: players,
+            mode: GameMode::Teams,
+            partner_exchange: true,
+            ..Default::default()
+        };
+        let mut game = Game::new(rules, &DeckSpec::classic());
+        for _ in 0..players {
+            game.add_player().unwrap();
+        }
+        game
+    }
+
+    #[test]
+    fn test_teams_are_balanced() {
+        let game = team_game(4);
+        let teams: Vec<_> = game.players.iter().map(|p| p.team).collect();
+        assert_eq!(teams, [Some(0), Some(1), Some(0), Some(1)]);
+        assert_eq!(game.partner(0), Some(2));
+        assert_eq!(game.partner(3), Some(1));
+    }
+
+    #[test]
+    fn test_set_team() {
+        let mut game = team_game(4);
+        let set = |player, team| Action::SetTeam { player, team };
+        assert_eq!(game.apply(set(1, 0)), Err(GameError::InvalidTeam));
+        assert_eq!(game.apply(set(1, 2)), Err(GameError::InvalidTeam));
+        game.players[2].team = Some(1);
+        assert_eq!(game.apply(Action::Start), Err(GameError::UnevenTeams));
+        game.apply(set(1, 0)).unwrap();
+        game.apply(Action::Start).unwrap();
+        assert_eq!(game.apply(set(1, 1)), Err(GameError::AlreadyStarted));
+    }
+
+    #[test]
+    fn test_team_scores_opponents_hands() {
+        let mut game = team_game(4);
+        let hands = [
+            vec![red(1)],
+            vec![red(2), red(3)],
+            vec![Card::plus_four(0)],
+            vec![red(4)],
+        ];
+        for (p, cards) in game.players.iter_mut().zip(hands) {
+            p.cards = cards;
+        }
+        game.state.played_cards = vec![red(0)];
+        game.started = true;
+        let events = game.apply(play(0, vec![0])).unwrap();
+        assert_eq!(
+            events.last(),
+            Some(&GameEvent::RoundOver {
+                winner: 0,
+                team: Some(0),
+                points: 9,
+                hand_points: vec![0, 5, 50, 4]
+            })
+        );
+    }
+
+    #[test]
+    fn test_give_to_partner() {
+        let mut game = team_game(4);
+        game.apply(Action::Start).unwrap();
+        let give = |player| Action::GiveToPartner { player, card: 0 };
+        let card = game.players[1].cards[0].clone();
+        assert_eq!(
+            game.apply(give(1)),
+            Ok(vec![GameEvent::CardGiven {
+                player: 1,
+                partner: 3
+            }])
+        );
+        assert_eq!(game.players[1].cards.len(), 6);
+        assert_eq!(game.players[3].cards.last(), Some(&card));
+        assert_eq!(game.apply(give(1)), Err(GameError::AlreadyGaveCard));
+    }
+
+    fn elimination_game(players: usize) -> Game {
+        let rules = Rules {
+            max_players: players,
+            mode: GameMode::Elimination,
+            ..Default::default()
+        };
+        let mut game = Game::new(rules, &DeckSpec::classic());
+        for _ in 0..players {
+            game.add_player().unwrap();
+        }
+        game.apply(Action::Start).unwrap();
+        game
+    }
+
+    #[test]
+    fn test_elimination() {
+        let mut game = elimination_game(3);
+        game.players[0].cards = vec![red(1)];
+        game.players[1].cards = vec![Card::plus_four(0)];
+        game.players[2].cards = vec![red(9)];
+        game.state.played_cards.push(red(0));
+        let events = game.apply(play(0, vec![0])).unwrap();
+        assert!(events.contains(&GameEvent::Eliminated { player: 1 }));
+        assert!(game.players[1].eliminated);
+        assert!(game.players[1].cards.is_empty());
+        assert!(!game.game_over);
+
+        // The next round skips the eliminated player
+        assert_eq!(game.apply(Action::Start), Ok(vec![GameEvent::Started]));
+        assert!(game.players[1].cards.is_empty());
+        assert_eq!(game.state.turn_index, 0);
+        game.players[0].cards.push(red(2));
+        game.state.played_cards.push(red(3));
+        game.apply(play(0, vec![game.players[0].cards.len() - 1]))
+            .unwrap();
+        assert_eq!(game.state.turn_index, 2);
+        let target = Action::PlayTargetedCard {
+            player: 2,
+            card: 0,
+            color: Color::Red,
+            target: 1,
+        };
+        game.players[2].cards.insert(0, Card::swap_hands(0));
+        assert_eq!(game.apply(target), Err(GameError::InvalidTarget));
+    }
+
+    #[test]
+    fn test_elimination_game_over() {
+        let mut game = elimination_game(2);
+        game.players[0].cards = vec![red(1)];
+        game.players[1].cards = vec![red(5)];
+        game.state.played_cards.push(red(0));
+        let events = game.apply(play(0, vec![0])).unwrap();
+        assert_eq!(
+            events[events.len() - 2..],
+            [
+                GameEvent::Eliminated { player: 1 },
+                GameEvent::GameOver { winner: 0 }
+            ]
+        );
+        assert_eq!(game.apply(Action::Start), Err(GameError::GameOver));
+    }
+
+    #[test]
+    fn test_next_round() {
+        let mut game = create_game(vec![vec![red(1)], vec![red(2), red(3)]], red(0));
+        let total = all_cards_len(&game);
+        game.apply(play(0, vec![0])).unwrap();
+        assert!(game.round_over);
+        game.apply(Action::Start).unwrap();
+        assert!(!game.round_over);
+        assert!(game.players.iter().all(|p| p.cards.len() == 7));
+        assert_eq!(all_cards_len(&game), total);
+    }
+
+    #[test]
+    fn test_reshuffled_wilds_lose_their_color() {
+        let hands = vec![
+            vec![Card::change_color(1), red(2)],
+            vec![Card::number(3, Color::Blue, 2), red(4)],
+        ];
+        let mut game = create_game(hands, red(0));
+        game.state.unplayed_cards.clear();
+        game.apply(Action::PlaySpecialCard {
+            player: 0,
+            card: 0,
+            color: Color::Blue,
+        })
+        .unwrap();
+        game.apply(play(1, vec![0])).unwrap();
+        // Everything under the blue 3 gets reshuffled, the wild with it
+        game.apply(Action::TakeCard { player: 0 }).unwrap();
+        assert_eq!(wild(&mut game).color, Color::None);
+
+        // Gathering the cards for the next round does the same
+        wild(&mut game).color = Color::Green;
+        game.collect_cards();
+        assert_eq!(wild(&mut game).color, Color::None);
+    }
+
+    /// The wild card with id 1, in a hand or the draw pile
+    fn wild(game: &mut Game) -> &mut Card {
+        game.players
+            .iter_mut()
+            .flat_map(|p| &mut p.cards)
+            .chain(&mut game.state.unplayed_cards)
+            .find(|c| c.id == 1)
+            .unwrap()
+    }
+
+    fn all_cards_len(game: &Game) -> usize {
+        game.state.played_cards.len()
+            + game.state.unplayed_cards.len()
+            + game.players.iter().map(|p| p.cards.len()).sum::<usize>()
+    }
+
+    #[test]
+    fn test_remove_player_before_start() {
+        let mut game = Game::new(Rules::default(), &DeckSpec::classic());
+        game.add_player().unwrap();
+        game.add_player().unwrap();
+        game.remove_player(0).unwrap();
+        assert_eq!(game.players.len(), 1);
+        assert_eq!(game.remove_player(1), Err(GameError::UnknownPlayer));
+    }
+}
@@ -1,16 +1,27 @@
-use std::{collections::HashSet, sync::Arc};
+use serde::{Deserialize, Serialize};
 
-use crate::user::User;
+use super::{Card, CardKind, Color, State, TurnDirection};
 
-use super::{Card, CardKind, State};
-
-#[derive(Debug)]
+/// A player's hand, the connection belongs to the room
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Player {
     pub cards: Vec<Card>,
-    pub tx: tokio::sync::mpsc::Sender<String>,
-    pub user: Arc<User>,
+    /// Only set when playing in teams
+    pub team: Option<usize>,
+    /// Whether the player has given their partner a card this round
+    pub gave_card: bool,
+    /// Knocked out in elimination mode, the player only spectates
+    pub eliminated: bool,
 }
 impl Player {
+    /// Removes and returns every card of `color` from the hand
+    pub fn take_color(&mut self, color: Color) -> Vec<Card> {
+        let (taken, kept) = std::mem::take(&mut self.cards)
+            .into_iter()
+            .partition(|c| c.color == color);
+        self.cards = kept;
+        taken
+    }
     pub fn can_play_card(&self, state: &State) -> bool {
         self.cards.iter().any(|c| state.can_play(c))
     }
@@ -30,7 +41,7 @@ impl Player {
         }
         let mut it = card_indeces.windows(2);
 
-        while let Some(&[l, r]) = dbg!(it.next()) {
+        while let Some(&[l, r]) = it.next() {
             let l = &self.cards[l];
             if matches!(l.kind, CardKind::Special(_)) || l.kind != self.cards[r].kind {
                 return false;
@@ -40,25 +51,42 @@ impl Player {
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use std::sync::Arc;
+/// Swaps the hands of the players at indices `a` and `b`
+pub fn swap_hands(players: &mut [Player], a: usize, b: usize) {
+    let hand = std::mem::take(&mut players[a].cards);
+    players[a].cards = std::mem::replace(&mut players[b].cards, hand);
+}
 
-    use tokio::sync::mpsc;
+/// Passes every player's hand to the next player in `direction`, skipping eliminated players
+pub fn rotate_hands(players: &mut [Player], direction: TurnDirection) {
+    let mut hands: Vec<_> = players
+        .iter_mut()
+        .filter(|p| !p.eliminated)
+        .map(|p| std::mem::take(&mut p.cards))
+        .collect();
+    if hands.is_empty() {
+        return;
+    }
+    match direction {
+        // Clockwise turns go towards lower indices
+        TurnDirection::Clockwise => hands.rotate_left(1),
+        TurnDirection::CounterClockwise => hands.rotate_right(1),
+    }
+    for (player, hand) in players.iter_mut().filter(|p| !p.eliminated).zip(hands) {
+        player.cards = hand;
+    }
+}
 
-    use crate::{
-        game::{Card, Color, State},
-        user::User,
-    };
+#[cfg(test)]
+mod tests {
+    use crate::game::{Card, CardKind, Color, NormalCardKind, State, TurnDirection};
 
-    use super::Player;
+    use super::{rotate_hands, swap_hands, Player};
 
     fn create_player(cards: Vec<Card>) -> Player {
-        let (tx, _) = mpsc::channel(1);
         Player {
-            tx,
             cards,
-            user: Arc::new(User::new_empty()),
+            ..Default::default()
         }
     }
     #[test]
@@ -76,4 +104,58 @@ mod tests {
         assert!(!player.can_play_consecutive_cards(&state, &[3, 1, 2]));
         assert!(!player.can_play_consecutive_cards(&state, &[2, 1, 0, 2]));
     }
+
+    #[test]
+    fn test_take_color() {
+        let mut player = create_player(vec![
+            Card::number(1, Color::Red, 0),
+            Card::number(2, Color::Green, 1),
+            Card::reverse(Color::Red, 2),
+        ]);
+        let taken = player.take_color(Color::Red);
+        assert_eq!(
+            taken,
+            vec![Card::number(1, Color::Red, 0), Card::reverse(Color::Red, 2)]
+        );
+        assert_eq!(player.cards, vec![Card::number(2, Color::Green, 1)]);
+    }
+
+    /// Three players, each holding a single card numbered after their index
+    fn create_players() -> Vec<Player> {
+        (0..3)
+            .map(|i| create_player(vec![Card::number(i as u8, Color::Red, 0)]))
+            .collect()
+    }
+
+    fn hand_numbers(players: &[Player]) -> Vec<u8> {
+        players
+            .iter()
+            .map(|p| match p.cards[0].kind {
+                CardKind::Normal(NormalCardKind::Number(n)) => n,
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_swap_hands() {
+        let mut players = create_players();
+        swap_hands(&mut players, 0, 2);
+        assert_eq!(hand_numbers(&players), [2, 1, 0]);
+    }
+
+    #[test]
+    fn test_rotate_hands_clockwise() {
+        let mut players = create_players();
+        rotate_hands(&mut players, TurnDirection::Clockwise);
+        // Player 0's hand goes to the next player clockwise, which is player 2
+        assert_eq!(hand_numbers(&players), [1, 2, 0]);
+    }
+
+    #[test]
+    fn test_rotate_hands_counter_clockwise() {
+        let mut players = create_players();
+        rotate_hands(&mut players, TurnDirection::CounterClockwise);
+        assert_eq!(hand_numbers(&players), [2, 0, 1]);
+    }
 }
@@ -0,0 +1,257 @@
+//! Random action sequences against the game, checking that no cards are lost or duplicated
+
+use proptest::{collection::vec, prelude::*};
+
+use super::{Action, Card, CardKind, Color, DeckSpec, Game, GameMode, Rules, State};
+
+/// Either a raw action, which is usually illegal, or a legal move for whoever has the turn
+#[derive(Clone, Debug)]
+enum Step {
+    Raw(Action),
+    Legal {
+        choice: usize,
+        color: Color,
+        target: usize,
+    },
+}
+
+fn color() -> impl Strategy<Value = Color> {
+    prop_oneof![
+        Just(Color::Red),
+        Just(Color::Green),
+        Just(Color::Blue),
+        Just(Color::Yellow),
+        Just(Color::None),
+    ]
+}
+
+fn action(players: usize) -> impl Strategy<Value = Action> {
+    let player = 0..players + 1;
+    let card = 0..12usize;
+    prop_oneof![
+        Just(Action::Start),
+        (player.clone(), vec(card.clone(), 0..4))
+            .prop_map(|(player, cards)| Action::PlayCards { player, cards }),
+        (player.clone(), card.clone(), color()).prop_map(|(player, card, color)| {
+            Action::PlaySpecialCard {
+                player,
+                card,
+                color,
+            }
+        }),
+        (player.clone(), card.clone(), color(), 0..players + 1).prop_map(
+            |(player, card, color, target)| Action::PlayTargetedCard {
+                player,
+                card,
+                color,
+                target,
+            }
+        ),
+        player
+            .clone()
+            .prop_map(|player| Action::TakeCard { player }),
+        player.clone().prop_map(|player| Action::Pass { player }),
+        (player.clone(), 0..players + 1)
+            .prop_map(|(player, target)| Action::ChooseSwapTarget { player, target }),
+        (player.clone(), 0..4usize).prop_map(|(player, team)| Action::SetTeam { player, team }),
+        (player.clone(), card.clone())
+            .prop_map(|(player, card)| Action::GiveToPartner { player, card }),
+        (player, card, 0..200usize).prop_map(|(player, card, cards_played)| {
+            Action::JumpIn {
+                player,
+                card,
+                cards_played,
+            }
+        }),
+    ]
+}
+
+fn step(players: usize) -> impl Strategy<Value = Step> {
+    prop_oneof![
+        1 => action(players).prop_map(Step::Raw),
+        3 => (any::<usize>(), color(), 0..players).prop_map(|(choice, color, target)| Step::Legal {
+            choice,
+            color,
+            target,
+        }),
+    ]
+}
+
+fn mode() -> impl Strategy<Value = GameMode> {
+    prop_oneof![
+        Just(GameMode::Classic),
+        Just(GameMode::Teams),
+        Just(GameMode::Elimination),
+    ]
+}
+
+fn deck() -> impl Strategy<Value = DeckSpec> {
+    prop_oneof![
+        Just(DeckSpec::classic()),
+        Just(DeckSpec::small()),
+        Just(DeckSpec::party()),
+    ]
+}
+
+/// The rules, deck and player count of a game, with the steps to run against it
+fn game() -> impl Strategy<Value = (Rules, DeckSpec, usize, Vec<Step>)> {
+    (
+        2..=6usize,
+        1..=7usize,
+        any::<bool>(),
+        any::<bool>(),
+        mode(),
+        deck(),
+    )
+        .prop_flat_map(|(players, hand_size, seven_o, jump_in, mode, deck)| {
+            let rules = Rules {
+                max_players: players,
+                hand_size,
+                seven_o,
+                jump_in,
+                mode,
+                partner_exchange: mode == GameMode::Teams,
+                ..Default::default()
+            };
+            (
+                Just(rules),
+                Just(deck),
+                Just(players),
+                vec(step(players), 0..150),
+            )
+        })
+}
+
+/// Turns a legal step into the action the current player would take
+fn legal_action(game: &Game, choice: usize, color: Color, target: usize) -> Action {
+    let player = game.state.turn_index;
+    // Never target yourself
+    let target = if target == player {
+        (target + 1) % game.players.len()
+    } else {
+        target
+    };
+    if !game.started || game.round_over {
+        return Action::Start;
+    }
+    if game.awaiting_swap_target {
+        return Action::ChooseSwapTarget { player, target };
+    }
+    let playable: Vec<usize> = game.players[player]
+        .cards
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| game.state.can_play(c))
+        .map(|(i, _)| i)
+        .collect();
+    if playable.is_empty() {
+        return Action::TakeCard { player };
+    }
+    let card = playable[choice % playable.len()];
+    let color = if color == Color::None {
+        Color::Red
+    } else {
+        color
+    };
+    match game.players[player].cards[card].kind {
+        CardKind::Special(k) if k.needs_target() => Action::PlayTargetedCard {
+            player,
+            card,
+            color,
+            target,
+        },
+        CardKind::Special(_) => Action::PlaySpecialCard {
+            player,
+            card,
+            color,
+        },
+        CardKind::Normal(_) => Action::PlayCards {
+            player,
+            cards: vec![card],
+        },
+    }
+}
+
+fn all_cards(game: &Game) -> Vec<&Card> {
+    game.state
+        .played_cards
+        .iter()
+        .chain(&game.state.unplayed_cards)
+        .chain(game.players.iter().flat_map(|p| &p.cards))
+        .collect()
+}
+
+fn check_invariants(game: &Game, deck_size: usize) -> Result<(), TestCaseError> {
+    let cards = all_cards(game);
+    prop_assert_eq!(cards.len(), deck_size);
+    let mut ids: Vec<u8> = cards.iter().map(|c| c.id).collect();
+    ids.sort();
+    ids.dedup();
+    prop_assert_eq!(ids.len(), deck_size, "a card was duplicated");
+    if game.started {
+        prop_assert!(game.state.turn_index < game.players.len());
+        prop_assert!(game
+            .players
+            .iter()
+            .filter(|p| p.eliminated)
+            .all(|p| p.cards.is_empty()));
+    }
+    // A failed start between rounds leaves every card gathered in the pile
+    if game.started && !game.round_over {
+        prop_assert!(!game.state.played_cards.is_empty());
+        prop_assert!(!game.players[game.state.turn_index].eliminated);
+    }
+    Ok(())
+}
+
+proptest! {
+    #[test]
+    fn cards_are_conserved((rules, deck, players, steps) in game()) {
+        let mut game = Game::new(rules, &deck);
+        for _ in 0..players {
+            game.add_player().unwrap();
+        }
+        let deck_size = deck.card_count();
+        for step in steps {
+            let action = match step {
+                Step::Raw(action) => action,
+                Step::Legal { choice, color, target } => legal_action(&game, choice, color, target),
+            };
+            let cards_played = game.cards_played;
+            let round_over = game.round_over;
+            let is_start = action == Action::Start;
+            if game.apply(action).is_ok() {
+                prop_assert!(!round_over || is_start, "an action was accepted after the round ended");
+                prop_assert!(game.cards_played >= cards_played);
+            }
+            check_invariants(&game, deck_size)?;
+        }
+    }
+
+    #[test]
+    fn draw_card_never_panics(deck in deck(), played in 0..40usize, draws in 0..200usize) {
+        let mut cards = deck.build();
+        let played = played.min(cards.len());
+        let played_cards = cards.split_off(cards.len() - played);
+        let mut state = State {
+            played_cards,
+            unplayed_cards: cards,
+            ..Default::default()
+        };
+        let total = deck.card_count();
+        let top = state.played_cards.last().cloned();
+        let mut drawn = Vec::new();
+        for _ in 0..draws {
+            let drawable = state.drawable();
+            match state.draw_card() {
+                Some(card) => drawn.push(card),
+                None => prop_assert_eq!(drawable, 0),
+            }
+            prop_assert_eq!(state.played_cards.last().cloned(), top.clone());
+        }
+        prop_assert_eq!(
+            drawn.len() + state.unplayed_cards.len() + state.played_cards.len(),
+            total
+        );
+    }
+}
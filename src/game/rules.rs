@@ -0,0 +1,60 @@
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use super::CLASSIC_DECK;
+
+#[derive(TS, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[ts(export)]
+pub enum GameMode {
+    /// Everyone plays for themselves
+    #[default]
+    Classic,
+    /// Players are split into teams of two that win together
+    Teams,
+    /// The player with the most points at the end of a round is out, until one is left
+    Elimination,
+}
+
+/// Settings a room is created with, sent to clients on connect
+#[derive(TS, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct Rules {
+    pub max_players: usize,
+    /// How many cards each player is dealt at the start
+    pub hand_size: usize,
+    /// Name of the deck the game is played with
+    pub deck: String,
+    /// Playing a 7 swaps hands with a chosen player, playing a 0 rotates everyone's hands
+    pub seven_o: bool,
+    /// Players can play an exact duplicate of the top card out of turn
+    pub jump_in: bool,
+    pub mode: GameMode,
+    /// Partners can give each other one card per round
+    pub partner_exchange: bool,
+}
+
+impl Rules {
+    pub fn teams(&self) -> bool {
+        self.mode == GameMode::Teams
+    }
+
+    /// Seats are split into this many teams when playing in teams
+    pub fn team_count(&self) -> usize {
+        self.max_players / 2
+    }
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self {
+            max_players: 4,
+            hand_size: 7,
+            deck: CLASSIC_DECK.into(),
+            seven_o: false,
+            jump_in: false,
+            mode: GameMode::Classic,
+            partner_exchange: false,
+        }
+    }
+}
@@ -1,14 +1,45 @@
 use crate::{game::*, user::User};
 use serde::{Deserialize, Serialize};
 use ts_rs::TS;
+
+/// Every protocol version the server can talk to
+pub const SUPPORTED_VERSIONS: &[u32] = &[1];
+
 #[derive(Clone, Debug, TS, Deserialize)]
 #[ts(export)]
 #[serde(tag = "tag", content = "fields")]
 pub enum Request {
     PlaySpecialCard(usize, Color),
     PlayCards(Vec<usize>),
+    /// Plays a special card that targets another player, like `SwapHands`
+    PlayTargetedCard {
+        card: usize,
+        color: Color,
+        target: usize,
+    },
+    /// Picks who to swap hands with after playing a 7 with the seven-o rule
+    ChooseSwapTarget(usize),
+    /// Plays an exact duplicate of the top card out of turn. `cards_played` is the count from
+    /// the last game state the client got
+    JumpIn {
+        card: usize,
+        cards_played: usize,
+    },
+    /// Gives a card to the player's partner when playing in teams, once per round
+    GiveToPartner(usize),
     TakeCard,
-    SendMessage { content: String },
+    SendMessage {
+        content: String,
+    },
+    /// Must be the first message sent after the socket is opened
+    Hello {
+        protocol_version: u32,
+        client_name: String,
+        /// Needed to join lobbies with a password
+        #[serde(default)]
+        #[ts(optional = nullable)]
+        password: Option<String>,
+    },
 }
 
 #[derive(Clone, Debug, TS, Serialize)]
@@ -17,7 +48,21 @@ pub enum Request {
 pub enum Response<'a> {
     ChatMessage(ChatMessage<'a>),
     GameState(GameState<'a>),
-    // Error(String),
+    Welcome(Welcome<'a>),
+    Error(String),
+    /// The server is about to stop, the socket closes after this many seconds
+    ServerShutdown {
+        in_seconds: u32,
+    },
+}
+
+#[derive(Clone, Debug, TS, Serialize)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+pub struct Welcome<'a> {
+    pub protocol_version: u32,
+    pub supported_versions: &'a [u32],
+    pub rules: &'a Rules,
 }
 
 #[derive(Clone, Debug, TS, Serialize)]
@@ -40,6 +85,14 @@ pub struct GameState<'a> {
     pub self_index: usize,
     pub cards_played: usize,
     pub last_played_cards: &'a [Card],
+    /// The current player played a 7 and has to choose who to swap hands with
+    pub awaiting_swap_target: bool,
+    /// Someone went out or the piles ran out, `/start` deals the next round
+    pub round_over: bool,
+    /// Only one player is left in elimination mode
+    pub game_over: bool,
+    /// What happened since the last game state
+    pub events: &'a [GameEvent],
 }
 
 #[derive(Clone, Debug, TS, Serialize)]
@@ -48,4 +101,10 @@ pub struct GameState<'a> {
 pub struct PlayerInfo<'a> {
     pub user: &'a User,
     pub card_count: usize,
+    pub connected: bool,
+    pub latency_ms: Option<u32>,
+    /// Only set when playing in teams
+    pub team: Option<usize>,
+    /// Knocked out in elimination mode, the player only spectates
+    pub eliminated: bool,
 }
@@ -1,30 +1,274 @@
-use std::sync::Arc;
+use std::{collections::HashMap, convert::Infallible, fmt, net::SocketAddr, sync::Arc};
 
 use axum::{
-    extract::{Path, State, WebSocketUpgrade},
+    extract::{ConnectInfo, Path, Query, State, WebSocketUpgrade},
     http::StatusCode,
-    response::{IntoResponse, Response},
-    routing::get,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::{delete, get},
     Json, Router,
 };
+use futures_util::{stream, Stream};
 use serde::{Deserialize, Serialize};
-use tokio::sync::mpsc;
+use tokio::sync::{broadcast, mpsc, watch};
 use tracing::info;
 use ts_rs::TS;
 use uuid::Uuid;
 
-use crate::{handle_socket, room::RoomActor, token_extractor::SessionToken, Command, SharedState};
+use crate::{
+    game::{DeckSpec, GameMode, Rules, CLASSIC_DECK},
+    room::RoomActor,
+    shutdown,
+    socket::{handle_socket, Lock, MAX_REQUEST_SIZE},
+    token_extractor::SessionToken,
+    Command, Ser, SharedState,
+};
 
-#[derive(Deserialize, Debug, TS)]
+#[derive(Deserialize, TS)]
 #[ts(export)]
 struct CreateLobbyData {
     name: String,
     max_players: usize,
+    /// Defaults to the classic deck
+    deck: Option<String>,
+    #[serde(default)]
+    seven_o: bool,
+    #[serde(default)]
+    jump_in: bool,
+    /// Teams need 4 or 6 players
+    #[serde(default)]
+    mode: GameMode,
+    #[serde(default)]
+    partner_exchange: bool,
+    /// Private lobbies aren't listed, they're joined with the id or invite code
+    #[serde(default)]
+    private: bool,
+    password: Option<String>,
+}
+
+/// Leaves out the password so it doesn't end up in the logs
+impl fmt::Debug for CreateLobbyData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CreateLobbyData")
+            .field("name", &self.name)
+            .field("max_players", &self.max_players)
+            .field("deck", &self.deck)
+            .field("seven_o", &self.seven_o)
+            .field("jump_in", &self.jump_in)
+            .field("mode", &self.mode)
+            .field("partner_exchange", &self.partner_exchange)
+            .field("private", &self.private)
+            .field("password", &self.password.as_ref().map(|_| "<hidden>"))
+            .finish()
+    }
+}
+
+#[derive(Serialize, Debug, TS)]
+#[ts(export)]
+struct CreatedLobby {
+    id: Uuid,
+    /// Short code others can join with
+    code: String,
 }
 
 pub struct Lobby {
     pub tx: mpsc::Sender<Command>,
     pub owner: Uuid,
+    /// Kept up to date by the room
+    pub data: watch::Receiver<LobbyData>,
+    pub private: bool,
+    /// Needed to join if set
+    pub password: Option<String>,
+    /// Invite code, given out when the lobby is added to the app state
+    pub code: String,
+    /// A tournament or matchmaking table. The server opened it, so it doesn't count towards
+    /// the owner's lobbies and only closes on its own
+    pub table: bool,
+}
+
+/// Limits on the lobbies users can create
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LobbyLimits {
+    pub min_players: usize,
+    pub max_players: usize,
+    /// In characters
+    pub max_name_length: usize,
+    pub max_password_length: usize,
+    /// How many lobbies one user can own at a time
+    pub lobbies_per_user: usize,
+}
+
+impl Default for LobbyLimits {
+    fn default() -> Self {
+        Self {
+            min_players: 2,
+            max_players: 10,
+            max_name_length: 32,
+            max_password_length: 64,
+            lobbies_per_user: 3,
+        }
+    }
+}
+
+/// Characters allowed in lobby names besides letters and digits
+const NAME_PUNCTUATION: &[char] = &[' ', '-', '_', '\'', '!', '?', '.', '#'];
+
+#[derive(Serialize, Debug, PartialEq, TS)]
+#[ts(export)]
+pub struct FieldError {
+    /// The request field the error is about, missing for errors about the whole request
+    field: Option<&'static str>,
+    message: String,
+}
+
+impl FieldError {
+    pub fn new(field: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            field: Some(field),
+            message: message.into(),
+        }
+    }
+}
+
+/// The body of a 400 from creating a lobby or tournament, or joining the matchmaking queue
+#[derive(Serialize, Debug, TS)]
+#[ts(export)]
+pub struct ValidationErrors {
+    errors: Vec<FieldError>,
+}
+
+pub fn invalid(errors: Vec<FieldError>) -> Response {
+    (StatusCode::BAD_REQUEST, Json(ValidationErrors { errors })).into_response()
+}
+
+/// Checks a lobby or tournament name, `what` starts the error messages
+pub fn validate_name(name: &str, what: &str, limits: &LobbyLimits) -> Option<FieldError> {
+    let message = if name.trim().is_empty() {
+        format!("{what} name cannot be empty.")
+    } else if name.chars().count() > limits.max_name_length {
+        format!(
+            "{what} name can be at most {} characters.",
+            limits.max_name_length
+        )
+    } else if name.trim() != name {
+        format!("{what} name cannot start or end with a space.")
+    } else if !name
+        .chars()
+        .all(|c| c.is_alphanumeric() || NAME_PUNCTUATION.contains(&c))
+    {
+        format!("{what} name can only have letters, numbers, spaces and - _ ' ! ? . #")
+    } else {
+        return None;
+    };
+    Some(FieldError::new("name", message))
+}
+
+/// The game settings lobbies, the matchmaking queue and tournaments are all made with
+pub struct RuleChoices {
+    pub players: usize,
+    /// Defaults to the classic deck
+    pub deck: Option<String>,
+    pub seven_o: bool,
+    pub jump_in: bool,
+    pub mode: GameMode,
+    pub partner_exchange: bool,
+}
+
+impl RuleChoices {
+    /// Checks the choices against the limits and looks up the deck, adding any problems to
+    /// `errors`. Each request names the player count differently, its errors go to
+    /// `players_field`
+    pub fn validate(
+        self,
+        players_field: &'static str,
+        limits: &LobbyLimits,
+        decks: &HashMap<String, DeckSpec>,
+        errors: &mut Vec<FieldError>,
+    ) -> Option<(Rules, DeckSpec)> {
+        let before = errors.len();
+        if !(limits.min_players..=limits.max_players).contains(&self.players) {
+            errors.push(FieldError::new(
+                players_field,
+                format!(
+                    "Games need {} to {} players.",
+                    limits.min_players, limits.max_players
+                ),
+            ));
+        } else if self.mode == GameMode::Teams && !matches!(self.players, 4 | 6) {
+            errors.push(FieldError::new(players_field, "Teams need 4 or 6 players."));
+        }
+        if self.partner_exchange && self.mode != GameMode::Teams {
+            errors.push(FieldError::new(
+                "partner_exchange",
+                "Exchanging cards needs teams to be enabled.",
+            ));
+        }
+        let deck_name = self.deck.unwrap_or_else(|| CLASSIC_DECK.into());
+        let Some(deck) = decks.get(&deck_name) else {
+            errors.push(FieldError::new("deck", "Unknown deck."));
+            return None;
+        };
+        let rules = Rules {
+            max_players: self.players,
+            deck: deck_name,
+            seven_o: self.seven_o,
+            jump_in: self.jump_in,
+            mode: self.mode,
+            partner_exchange: self.partner_exchange,
+            ..Default::default()
+        };
+        // Everyone needs a full hand and there has to be a card left to start the pile with
+        if rules.max_players.saturating_mul(rules.hand_size) >= deck.card_count() {
+            errors.push(FieldError::new(
+                "deck",
+                "Deck is too small for that many players.",
+            ));
+        }
+        (errors.len() == before).then(|| (rules, deck.clone()))
+    }
+}
+
+impl CreateLobbyData {
+    /// Checks everything that doesn't depend on the other lobbies, returning the room's rules
+    fn validate(
+        &self,
+        limits: &LobbyLimits,
+        decks: &HashMap<String, DeckSpec>,
+    ) -> Result<(Rules, DeckSpec), Vec<FieldError>> {
+        let mut errors = Vec::new();
+        errors.extend(validate_name(&self.name, "Lobby", limits));
+        let choices = RuleChoices {
+            players: self.max_players,
+            deck: self.deck.clone(),
+            seven_o: self.seven_o,
+            jump_in: self.jump_in,
+            mode: self.mode,
+            partner_exchange: self.partner_exchange,
+        };
+        let valid = choices.validate("max_players", limits, decks, &mut errors);
+        match &self.password {
+            Some(p) if p.is_empty() => {
+                errors.push(FieldError::new("password", "Password cannot be empty."))
+            }
+            Some(p) if p.chars().count() > limits.max_password_length => {
+                errors.push(FieldError::new(
+                    "password",
+                    format!(
+                        "Password can be at most {} characters.",
+                        limits.max_password_length
+                    ),
+                ))
+            }
+            _ => (),
+        }
+        match valid {
+            Some(valid) if errors.is_empty() => Ok(valid),
+            _ => Err(errors),
+        }
+    }
 }
 
 async fn lobbies_create(
@@ -33,50 +277,477 @@ async fn lobbies_create(
     Json(input): Json<CreateLobbyData>,
 ) -> Response {
     info!("New lobby {input:?}");
-    if input.name.is_empty() {
-        return (StatusCode::BAD_REQUEST, "Lobby name cannot be empty.").into_response();
+    if state.lock().shutting_down {
+        return (StatusCode::SERVICE_UNAVAILABLE, "Server is shutting down.").into_response();
+    }
+    if !state.lock().create_lobby_limiter.try_take(token) {
+        return (StatusCode::TOO_MANY_REQUESTS, "Too many lobbies created.").into_response();
+    }
+    let result = {
+        let state = state.lock();
+        input.validate(&state.lobby_limits, &state.decks)
+    };
+    let (rules, deck) = match result {
+        Ok(valid) => valid,
+        Err(errors) => return invalid(errors),
+    };
+    let (id, mut lobby) = RoomActor::spawn_new(&state, token, input.name, rules, deck);
+    lobby.private = input.private;
+    lobby.password = input.password;
+    let mut state = state.lock();
+    // Both checked when adding so parallel requests can't get around them, the room closes when
+    // dropped
+    let owned = state
+        .lobbies
+        .values()
+        .filter(|l| l.owner == token && !l.table)
+        .count();
+    if owned >= state.lobby_limits.lobbies_per_user {
+        return invalid(vec![FieldError {
+            field: None,
+            message: format!(
+                "You can have at most {} lobbies open.",
+                state.lobby_limits.lobbies_per_user
+            ),
+        }]);
     }
-    let fut = state.lock().collect_lobby_data();
-    if fut.await.iter().any(|i| i.name == input.name) {
-        return (StatusCode::BAD_REQUEST, "Lobby name already exists.").into_response();
+    if !state.add_lobby(id, lobby) {
+        return invalid(vec![FieldError::new("name", "Lobby name already exists.")]);
     }
-    let (tx, id) = RoomActor::spawn_new(input.name, input.max_players);
-    state.lock().lobbies.insert(id, Lobby { tx, owner: token });
-    (StatusCode::CREATED, Json(id)).into_response()
+    let code = state.lobbies[&id].code.clone();
+    (StatusCode::CREATED, Json(CreatedLobby { id, code })).into_response()
 }
 
-#[derive(Serialize, Debug, TS)]
+#[derive(Serialize, Debug, Clone, PartialEq, TS)]
 #[ts(export)]
 pub struct LobbyData {
     pub name: String,
     pub players: usize,
     pub max_players: usize,
     pub id: Uuid,
+    pub game_started: bool,
+    /// The owner's name, filled in when listing
+    pub owner: String,
+    /// Joining needs a password, filled in when listing
+    pub locked: bool,
+    pub rules: Rules,
+    /// The game was decided and nobody started another, finished lobbies aren't listed
+    pub finished: bool,
+}
+
+impl LobbyData {
+    /// Whether someone new can still sit down
+    fn open(&self) -> bool {
+        !self.game_started && self.players < self.max_players
+    }
+
+    fn free_seats(&self) -> usize {
+        self.max_players.saturating_sub(self.players)
+    }
 }
 
-async fn lobbies_list(State(state): State<SharedState>) -> Json<Vec<LobbyData>> {
-    let lobby_data = state.lock().collect_lobby_data();
-    Json(lobby_data.await)
+/// Pushed by `/lobbies/stream` when a public lobby changes
+#[derive(Serialize, Debug, Clone, TS)]
+#[serde(tag = "tag", content = "fields")]
+#[ts(export)]
+pub enum LobbyEvent {
+    Created(LobbyData),
+    /// Players joined or left or the game started
+    Updated(LobbyData),
+    Removed {
+        id: Uuid,
+    },
+    /// Events were missed, fetch the list again
+    Resync,
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+enum LobbySort {
+    #[default]
+    Name,
+    /// Fullest first
+    Players,
+    /// Most open seats first
+    OpenSeats,
+}
+
+/// Filters for `GET /lobbies`, every one of them is optional
+#[derive(Deserialize, Debug, Default, TS)]
+#[ts(export)]
+struct LobbyQuery {
+    /// Only lobbies that can still be joined, or only ones that can't
+    open: Option<bool>,
+    /// Only lobbies that can still be joined with at least this many seats free, for groups
+    min_seats: Option<usize>,
+    started: Option<bool>,
+    mode: Option<GameMode>,
+    deck: Option<String>,
+    seven_o: Option<bool>,
+    jump_in: Option<bool>,
+    /// Part of the name, ignoring case
+    name: Option<String>,
+    #[serde(default)]
+    sort: LobbySort,
+    /// The `next` of the previous page
+    cursor: Option<String>,
+    limit: Option<usize>,
+}
+
+#[derive(Serialize, Debug, TS)]
+#[ts(export)]
+struct LobbyPage {
+    lobbies: Vec<LobbyData>,
+    /// Pass as `cursor` to get the next page, missing on the last page
+    next: Option<String>,
+}
+
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 100;
+
+impl LobbyQuery {
+    fn matches(&self, lobby: &LobbyData) -> bool {
+        let rules = &lobby.rules;
+        self.open.is_none_or(|open| lobby.open() == open)
+            && self
+                .min_seats
+                .is_none_or(|n| lobby.open() && lobby.free_seats() >= n)
+            && self.started.is_none_or(|s| lobby.game_started == s)
+            && self.mode.is_none_or(|m| rules.mode == m)
+            && self.deck.as_ref().is_none_or(|d| rules.deck == *d)
+            && self.seven_o.is_none_or(|s| rules.seven_o == s)
+            && self.jump_in.is_none_or(|j| rules.jump_in == j)
+            && self
+                .name
+                .as_ref()
+                .is_none_or(|n| lobby.name.to_lowercase().contains(&n.to_lowercase()))
+    }
+
+    /// Lobbies are ordered by this, names are unique so no two lobbies share a key
+    fn sort_key<'a>(&self, lobby: &'a LobbyData) -> (usize, &'a str) {
+        match self.sort {
+            LobbySort::Name => (0, &lobby.name),
+            LobbySort::Players => (usize::MAX - lobby.players, &lobby.name),
+            LobbySort::OpenSeats => (usize::MAX - lobby.free_seats(), &lobby.name),
+        }
+    }
+
+    fn page(&self, mut lobbies: Vec<LobbyData>) -> Result<LobbyPage, &'static str> {
+        let after = match &self.cursor {
+            Some(cursor) => {
+                let (n, name) = cursor.split_once(':').ok_or("Invalid cursor.")?;
+                Some((n.parse::<usize>().map_err(|_| "Invalid cursor.")?, name))
+            }
+            None => None,
+        };
+        let limit = self
+            .limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE);
+        lobbies.retain(|l| self.matches(l) && after.is_none_or(|a| self.sort_key(l) > a));
+        lobbies.sort_by(|a, b| self.sort_key(a).cmp(&self.sort_key(b)));
+        let next = (lobbies.len() > limit).then(|| {
+            let (n, name) = self.sort_key(&lobbies[limit - 1]);
+            format!("{n}:{name}")
+        });
+        lobbies.truncate(limit);
+        Ok(LobbyPage { lobbies, next })
+    }
+}
+
+async fn lobbies_list(
+    State(state): State<SharedState>,
+    Query(query): Query<LobbyQuery>,
+) -> Result<Json<LobbyPage>, (StatusCode, &'static str)> {
+    let lobbies = state.lock().lobby_data();
+    query
+        .page(lobbies)
+        .map(Json)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))
+}
+
+/// Server-sent events of every change to the public lobbies
+async fn lobbies_stream(
+    State(state): State<SharedState>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let (events, closing) = {
+        let state = state.lock();
+        (state.lobby_events.subscribe(), state.closing.subscribe())
+    };
+    // Ends when the server shuts down, graceful shutdown waits for every response to finish
+    let stream = stream::unfold((events, closing), |(mut events, mut closing)| async move {
+        let event = tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => event,
+                Err(broadcast::error::RecvError::Lagged(_)) => LobbyEvent::Resync,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            },
+            _ = shutdown::closing(&mut closing) => return None,
+        };
+        Some((Ok(Event::default().data(event.ser())), (events, closing)))
+    });
+    Sse::new(stream).keep_alive(KeepAlive::default())
 }
 
 async fn lobby_join(
     SessionToken(token): SessionToken,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
     State(state): State<SharedState>,
     ws: WebSocketUpgrade,
     Path(id): Path<Uuid>,
 ) -> Response {
-    let tx = match state.lock().lobbies.get(&id) {
-        Some(lobby) => lobby.tx.clone(),
+    // The password comes with the client's Hello, so it stays out of the URL and the logs
+    let (tx, password) = match state.lock().lobbies.get(&id) {
+        Some(lobby) => (lobby.tx.clone(), lobby.password.clone()),
         None => return (StatusCode::NOT_FOUND, "Lobby doesn't exist").into_response(),
     };
     let Some(user) = state.lock().users.get(&token).map(Arc::clone) else {
         return (StatusCode::UNAUTHORIZED).into_response();
     };
-    ws.on_upgrade(move |socket| handle_socket(socket, tx, user))
+    // Every guess takes a token up front, the handshake gives it back if the password is right
+    let lock = match password {
+        Some(_) if !state.lock().password_limiter.try_take(addr.ip()) => {
+            return (StatusCode::TOO_MANY_REQUESTS, "Too many wrong passwords").into_response()
+        }
+        Some(password) => Some(Lock {
+            password,
+            ip: addr.ip(),
+            app: SharedState::clone(&state),
+        }),
+        None => None,
+    };
+    let (limit, heartbeat, closing) = {
+        let state = state.lock();
+        (
+            state.rate_limits.socket,
+            state.heartbeat,
+            state.closing.subscribe(),
+        )
+    };
+    ws.max_message_size(MAX_REQUEST_SIZE)
+        .max_frame_size(MAX_REQUEST_SIZE)
+        .on_upgrade(move |socket| handle_socket(socket, tx, lock, user, limit, heartbeat, closing))
+}
+
+/// Finds the id of the lobby with the invite code
+async fn lobby_by_code(
+    State(state): State<SharedState>,
+    Path(code): Path<String>,
+) -> Result<Json<Uuid>, (StatusCode, &'static str)> {
+    state
+        .lock()
+        .lobby_codes
+        .get(&code.to_uppercase())
+        .map(|&id| Json(id))
+        .ok_or((StatusCode::NOT_FOUND, "No lobby with that code"))
+}
+
+/// Closes the lobby, only its owner can do this
+async fn lobby_delete(
+    SessionToken(token): SessionToken,
+    State(state): State<SharedState>,
+    Path(id): Path<Uuid>,
+) -> Response {
+    let tx = {
+        let mut state = state.lock();
+        match state.lobbies.get(&id) {
+            Some(lobby) if lobby.owner != token => {
+                return (StatusCode::FORBIDDEN, "Only the owner can close the lobby.")
+                    .into_response()
+            }
+            Some(lobby) if lobby.table => {
+                return (StatusCode::FORBIDDEN, "Tables close when their game ends.")
+                    .into_response()
+            }
+            Some(_) => state.remove_lobby(id).unwrap().tx,
+            None => return (StatusCode::NOT_FOUND, "Lobby doesn't exist").into_response(),
+        }
+    };
+    tx.send(Command::Shutdown).await.ok();
+    StatusCode::NO_CONTENT.into_response()
 }
 
 pub fn routes() -> Router<SharedState> {
     Router::new()
         .route("/join/:id", get(lobby_join))
+        .route("/code/:code", get(lobby_by_code))
+        .route("/stream", get(lobbies_stream))
+        .route("/:id", delete(lobby_delete))
         .route("/", get(lobbies_list).post(lobbies_create))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lobby(name: &str, players: usize, started: bool) -> LobbyData {
+        LobbyData {
+            name: name.into(),
+            players,
+            max_players: 4,
+            id: Uuid::new_v4(),
+            game_started: started,
+            owner: "owner".into(),
+            locked: false,
+            rules: Rules::default(),
+            finished: false,
+        }
+    }
+
+    fn names(page: &LobbyPage) -> Vec<&str> {
+        page.lobbies.iter().map(|l| l.name.as_str()).collect()
+    }
+
+    fn lobbies() -> Vec<LobbyData> {
+        let mut party = lobby("Party time", 2, false);
+        party.rules.seven_o = true;
+        vec![
+            lobby("office", 4, false),
+            party,
+            lobby("Lunch break", 1, true),
+            lobby("after work", 3, false),
+        ]
+    }
+
+    fn create(name: &str, max_players: usize) -> CreateLobbyData {
+        CreateLobbyData {
+            name: name.into(),
+            max_players,
+            deck: None,
+            seven_o: false,
+            jump_in: false,
+            mode: GameMode::Classic,
+            partner_exchange: false,
+            private: false,
+            password: None,
+        }
+    }
+
+    fn fields(input: &CreateLobbyData) -> Vec<Option<&'static str>> {
+        let decks = DeckSpec::presets().into_iter().collect();
+        match input.validate(&LobbyLimits::default(), &decks) {
+            Ok(_) => Vec::new(),
+            Err(errors) => errors.iter().map(|e| e.field).collect(),
+        }
+    }
+
+    #[test]
+    fn test_validate_player_count() {
+        assert!(fields(&create("office", 4)).is_empty());
+        assert_eq!(fields(&create("office", 1)), vec![Some("max_players")]);
+        assert_eq!(fields(&create("office", 11)), vec![Some("max_players")]);
+        // Too many to compare against the deck without overflowing
+        assert_eq!(
+            fields(&create("office", usize::MAX)),
+            vec![Some("max_players"), Some("deck")]
+        );
+    }
+
+    #[test]
+    fn test_validate_name() {
+        assert!(fields(&create("Friday night #3!", 4)).is_empty());
+        assert_eq!(fields(&create("  ", 4)), vec![Some("name")]);
+        assert_eq!(fields(&create(" office", 4)), vec![Some("name")]);
+        assert_eq!(fields(&create("<script>", 4)), vec![Some("name")]);
+        assert_eq!(fields(&create(&"a".repeat(33), 4)), vec![Some("name")]);
+    }
+
+    #[test]
+    fn test_validate_collects_every_error() {
+        let input = CreateLobbyData {
+            partner_exchange: true,
+            password: Some(String::new()),
+            ..create("", 0)
+        };
+        assert_eq!(
+            fields(&input),
+            vec![
+                Some("name"),
+                Some("max_players"),
+                Some("partner_exchange"),
+                Some("password")
+            ]
+        );
+        let input = CreateLobbyData {
+            deck: Some("nope".into()),
+            ..create("office", 4)
+        };
+        assert_eq!(fields(&input), vec![Some("deck")]);
+    }
+
+    #[test]
+    fn test_filters() {
+        let query = LobbyQuery {
+            open: Some(true),
+            ..Default::default()
+        };
+        assert_eq!(
+            names(&query.page(lobbies()).unwrap()),
+            vec!["Party time", "after work"]
+        );
+        let query = LobbyQuery {
+            started: Some(true),
+            ..Default::default()
+        };
+        assert_eq!(names(&query.page(lobbies()).unwrap()), vec!["Lunch break"]);
+        let query = LobbyQuery {
+            min_seats: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(names(&query.page(lobbies()).unwrap()), vec!["Party time"]);
+        let query = LobbyQuery {
+            seven_o: Some(true),
+            ..Default::default()
+        };
+        assert_eq!(names(&query.page(lobbies()).unwrap()), vec!["Party time"]);
+        let query = LobbyQuery {
+            name: Some("O".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            names(&query.page(lobbies()).unwrap()),
+            vec!["after work", "office"]
+        );
+    }
+
+    #[test]
+    fn test_sort() {
+        let query = LobbyQuery {
+            sort: LobbySort::Players,
+            ..Default::default()
+        };
+        assert_eq!(
+            names(&query.page(lobbies()).unwrap()),
+            vec!["office", "after work", "Party time", "Lunch break"]
+        );
+        let query = LobbyQuery {
+            sort: LobbySort::OpenSeats,
+            ..Default::default()
+        };
+        assert_eq!(
+            names(&query.page(lobbies()).unwrap()),
+            vec!["Lunch break", "Party time", "after work", "office"]
+        );
+    }
+
+    #[test]
+    fn test_pagination() {
+        let mut query = LobbyQuery {
+            sort: LobbySort::Players,
+            limit: Some(3),
+            ..Default::default()
+        };
+        let first = query.page(lobbies()).unwrap();
+        assert_eq!(names(&first), vec!["office", "after work", "Party time"]);
+        query.cursor = first.next;
+        // A lobby that sorts before the cursor doesn't shift the next page
+        let mut more = lobbies();
+        more.push(lobby("Busy", 4, false));
+        let second = query.page(more).unwrap();
+        assert_eq!(names(&second), vec!["Lunch break"]);
+        assert_eq!(second.next, None);
+        query.cursor = Some("nope".into());
+        assert!(query.page(lobbies()).is_err());
+    }
+}
@@ -1,49 +1,125 @@
+mod config;
 pub mod game;
 mod game_messages;
+mod matchmaking;
+mod rate_limit;
 mod room;
+mod shutdown;
+mod snapshot;
+mod socket;
 mod token_extractor;
+mod tournament;
 use std::{
     collections::{HashMap, HashSet},
-    io::Error,
+    io::{Error, ErrorKind},
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
     sync::Arc,
+    time::Duration,
 };
 
-use axum::{
-    extract::ws::{CloseFrame, Message, WebSocket},
-    Router,
-};
-use futures_util::{Future, SinkExt, StreamExt};
-use game::Color;
-use game_messages::Request;
-use lobby::{Lobby, LobbyData};
+use axum::{http::StatusCode, Router};
+use config::Config;
+use game::{Color, DeckSpec, Rules};
+use lobby::{Lobby, LobbyData, LobbyEvent, LobbyLimits};
+use matchmaking::Matchmaking;
 use parking_lot::Mutex;
+use rand::seq::SliceRandom;
+use rate_limit::{KeyedRateLimiter, RateLimits};
+use room::{RoomSettings, RoomState};
 use serde::Serialize;
+use socket::Heartbeat;
 use tokio::{
     net::TcpListener,
-    select,
-    sync::{mpsc, oneshot},
+    sync::{broadcast, mpsc, oneshot, watch},
 };
+use tournament::Tournament;
 use tower_http::trace::TraceLayer;
 use user::{User, UserCreate};
 use uuid::Uuid;
 type PlayerId = usize;
 
-use tracing::{self, info};
-use tracing_subscriber;
+use tracing::{self, error, info};
 
 static SESSION_TOKEN: &str = "SESSION_TOKEN";
-#[derive(Default)]
 struct AppState {
     lobbies: HashMap<Uuid, Lobby>,
+    /// Lobby names to their ids, names are unique
+    lobby_names: HashMap<String, Uuid>,
+    /// Invite codes to lobby ids
+    lobby_codes: HashMap<String, Uuid>,
+    /// Changes to public lobbies, for the lobby stream
+    lobby_events: broadcast::Sender<LobbyEvent>,
+    tournaments: HashMap<Uuid, Tournament>,
+    matchmaking: Matchmaking,
     users: HashMap<Uuid, Arc<User>>,
     taken_user_names: HashSet<String>,
     next_user_id: usize,
+    decks: HashMap<String, DeckSpec>,
+    rate_limits: RateLimits,
+    heartbeat: Heartbeat,
+    room_settings: RoomSettings,
+    login_limiter: KeyedRateLimiter<IpAddr>,
+    create_lobby_limiter: KeyedRateLimiter<Uuid>,
+    /// Tokens are taken when joining a lobby with a password and given back if it was right
+    password_limiter: KeyedRateLimiter<IpAddr>,
+    lobby_limits: LobbyLimits,
+    /// Whether the session cookie is only sent over HTTPS
+    secure_cookies: bool,
+    /// Set when the server starts shutting down, no new lobbies are made after that
+    shutting_down: bool,
+    /// Flips to true when every connection should close, see [`shutdown::shutdown`]
+    closing: Arc<watch::Sender<bool>>,
+    /// Where games in progress are saved, they aren't when this is unset
+    snapshot_path: Option<PathBuf>,
+}
+
+impl Default for AppState {
+    fn default() -> Self {
+        Self::new(RateLimits::default())
+    }
 }
 
 mod lobby;
 mod user;
+mod web;
 
 impl AppState {
+    fn new(rate_limits: RateLimits) -> Self {
+        Self {
+            lobbies: HashMap::new(),
+            lobby_names: HashMap::new(),
+            lobby_codes: HashMap::new(),
+            lobby_events: broadcast::channel(64).0,
+            tournaments: HashMap::new(),
+            matchmaking: Matchmaking::default(),
+            users: HashMap::new(),
+            taken_user_names: HashSet::new(),
+            next_user_id: 0,
+            decks: DeckSpec::presets().into_iter().collect(),
+            rate_limits,
+            heartbeat: Heartbeat::default(),
+            room_settings: RoomSettings::default(),
+            login_limiter: KeyedRateLimiter::new(rate_limits.login),
+            create_lobby_limiter: KeyedRateLimiter::new(rate_limits.create_lobby),
+            password_limiter: KeyedRateLimiter::new(rate_limits.password),
+            lobby_limits: LobbyLimits::default(),
+            secure_cookies: false,
+            shutting_down: false,
+            closing: Arc::new(watch::Sender::new(false)),
+            snapshot_path: None,
+        }
+    }
+    fn from_config(config: &Config) -> Self {
+        Self {
+            heartbeat: config.heartbeat(),
+            room_settings: config.room_settings(),
+            lobby_limits: config.lobby,
+            secure_cookies: config.secure_cookies,
+            snapshot_path: config.paths.snapshot.clone(),
+            ..Self::new(config.rate_limits)
+        }
+    }
     /// Adds a new new user if the name is free. Returns the user's generated session token on success
     fn new_user(&mut self, user: UserCreate) -> Option<Uuid> {
         info!("new_user: {}", user.name);
@@ -62,57 +138,200 @@ impl AppState {
             id
         })
     }
-    fn collect_lobby_data(&mut self) -> impl Future<Output = Vec<LobbyData>> + Send {
-        let senders = self
+    /// Adds the lobby unless another one already has its name. Gives it an invite code if it
+    /// doesn't have a free one already
+    fn add_lobby(&mut self, id: Uuid, mut lobby: Lobby) -> bool {
+        let name = lobby.data.borrow().name.clone();
+        if self.lobby_names.contains_key(&name) {
+            return false;
+        }
+        while lobby.code.is_empty() || self.lobby_codes.contains_key(&lobby.code) {
+            lobby.code = invite_code();
+        }
+        self.lobby_names.insert(name, id);
+        self.lobby_codes.insert(lobby.code.clone(), id);
+        if !lobby.private {
+            let data = self.listed_data(&lobby);
+            self.lobby_events.send(LobbyEvent::Created(data)).ok();
+        }
+        self.lobbies.insert(id, lobby);
+        true
+    }
+    /// The name, or the name with a number after it if that's taken, for rooms users didn't name
+    fn free_lobby_name(&self, name: String) -> String {
+        if !self.lobby_names.contains_key(&name) {
+            return name;
+        }
+        (2..)
+            .map(|i| format!("{name} ({i})"))
+            .find(|n| !self.lobby_names.contains_key(n))
+            .unwrap()
+    }
+    fn remove_lobby(&mut self, id: Uuid) -> Option<Lobby> {
+        let lobby = self.lobbies.remove(&id)?;
+        self.lobby_names.remove(&lobby.data.borrow().name);
+        self.lobby_codes.remove(&lobby.code);
+        if !lobby.private {
+            self.lobby_events.send(LobbyEvent::Removed { id }).ok();
+        }
+        self.matchmaking.matches.retain(|_, lobby| *lobby != id);
+        Some(lobby)
+    }
+    /// The public lobbies' latest data, rooms that died without removing themselves are dropped
+    fn lobby_data(&mut self) -> Vec<LobbyData> {
+        let dead: Vec<Uuid> = self
             .lobbies
+            .iter()
+            .filter(|(_, l)| l.data.has_changed().is_err())
+            .map(|(&id, _)| id)
+            .collect();
+        for id in dead {
+            self.remove_lobby(id);
+        }
+        self.lobbies
             .values()
-            .map(|l| l.tx.clone())
-            .collect::<Vec<_>>();
-        async move {
-            let mut results = Vec::new();
-            for lobby_tx in senders.into_iter() {
-                let (sender, receiver) = oneshot::channel();
-                lobby_tx.send(Command::GetData(sender)).await.unwrap();
-                results.push(receiver.await.unwrap())
-            }
-            results
+            .filter(|l| !l.private && !l.data.borrow().finished)
+            .map(|l| self.listed_data(l))
+            .collect()
+    }
+    /// The room's data with the parts only the app state knows filled in
+    fn listed_data(&self, lobby: &Lobby) -> LobbyData {
+        let owner = self.users.get(&lobby.owner).map(|u| u.name.clone());
+        LobbyData {
+            owner: owner.unwrap_or_default(),
+            locked: lobby.password.is_some(),
+            ..lobby.data.borrow().clone()
         }
     }
+    /// Tells the lobby stream about a change to a public lobby. Finishing takes it off the list,
+    /// starting another game puts it back
+    fn lobby_updated(&self, id: Uuid, was_finished: bool) {
+        let Some(lobby) = self.lobbies.get(&id).filter(|l| !l.private) else {
+            return;
+        };
+        let data = self.listed_data(lobby);
+        let event = match (was_finished, data.finished) {
+            (false, false) => LobbyEvent::Updated(data),
+            (false, true) => LobbyEvent::Removed { id },
+            (true, false) => LobbyEvent::Created(data),
+            (true, true) => return,
+        };
+        self.lobby_events.send(event).ok();
+    }
+}
+
+/// Letters and digits that can't be mistaken for each other
+const INVITE_CODE_CHARS: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const INVITE_CODE_LENGTH: usize = 6;
+
+fn invite_code() -> String {
+    let mut rng = rand::thread_rng();
+    (0..INVITE_CODE_LENGTH)
+        .map(|_| *INVITE_CODE_CHARS.choose(&mut rng).unwrap() as char)
+        .collect()
 }
 
 type SharedState = Arc<Mutex<AppState>>;
+/// The room's reply to `Command::Join`: the player's id, a receiver for messages sent to them
+/// and the room's rules
+pub type JoinResult = Result<(PlayerId, mpsc::Receiver<String>, Rules), String>;
 pub enum Command {
     SendMessage(PlayerId, String),
-    Join(
-        Arc<User>,
-        oneshot::Sender<Result<(PlayerId, mpsc::Receiver<String>), String>>,
-    ),
-    GetData(oneshot::Sender<LobbyData>),
+    Join(Arc<User>, oneshot::Sender<JoinResult>),
     Leave(PlayerId),
+    /// A heartbeat came back from the player after this long
+    Latency(PlayerId, Duration),
     PlayCard(PlayerId, usize, Color),
+    /// Plays a card that needs a target player, the last field is the target's index
+    PlayTargetedCard(PlayerId, usize, Color, usize),
+    ChooseSwapTarget(PlayerId, usize),
+    /// The last field is the `cards_played` count the player saw
+    JumpIn(PlayerId, usize, usize),
+    GiveToPartner(PlayerId, usize),
     PlayCards(PlayerId, Vec<usize>),
     TakeCard(PlayerId),
+    /// The server stops after this long, the room warns its players
+    ServerShutdown(Duration),
+    /// Asks for the room's game, if there's one in progress
+    Snapshot(oneshot::Sender<Option<RoomState>>),
     Shutdown,
     Noop,
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
-    tracing_subscriber::fmt()
-        .with_max_level(tracing::Level::DEBUG)
-        .init();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|a| a == "-h" || a == "--help") {
+        println!("{}", config::USAGE);
+        return Ok(());
+    }
+    let config = Config::load(args, std::env::vars()).unwrap_or_else(|e| {
+        eprintln!("{e}");
+        std::process::exit(2);
+    });
+    config.log.init();
 
     // Create the event loop and TCP listener we'll accept connections on.
-    let listener = TcpListener::bind("localhost:8080").await.unwrap();
+    let listener = TcpListener::bind(&config.bind).await.map_err(|e| {
+        error!("Can't listen on {}: {e}", config.bind);
+        e
+    })?;
+    info!("Listening on {}", config.bind);
+
+    let state = SharedState::new(Mutex::new(AppState::from_config(&config)));
+    if config.paths.decks.is_dir() {
+        let decks = DeckSpec::load_dir(&config.paths.decks).map_err(|e| {
+            error!("Invalid deck: {e}");
+            Error::new(ErrorKind::InvalidData, e)
+        })?;
+        for (name, deck) in decks {
+            info!("Loaded deck {name} with {} cards", deck.card_count());
+            state.lock().decks.insert(name, deck);
+        }
+    }
+    if let Some(path) = &config.paths.snapshot {
+        let snapshot = snapshot::load(path).map_err(|e| {
+            error!("{e}");
+            Error::new(ErrorKind::InvalidData, e)
+        })?;
+        if let Some(snapshot) = snapshot {
+            snapshot::restore(&state, snapshot);
+        }
+        tokio::spawn(snapshot::run(
+            SharedState::clone(&state),
+            path.clone(),
+            Duration::from_secs(config.snapshot_interval),
+        ));
+    }
+    tokio::spawn(matchmaking::run(SharedState::clone(&state)));
+    let mut app = app(SharedState::clone(&state), web::client(&config));
+    if let Some(cors) = config.cors() {
+        app = app.layer(cors);
+    }
+    let grace = Duration::from_secs(config.shutdown_grace);
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown::on_signal(state, grace))
+    .await
+    .unwrap();
+    Ok(())
+}
 
-    let state = SharedState::default();
-    let app = Router::new()
+/// The API under `/api`, everything else goes to the web client
+fn app(state: SharedState, client: Router) -> Router {
+    let api = Router::new()
         .nest("/user", user::routes())
         .nest("/lobbies", lobby::routes())
+        .nest("/tournaments", tournament::routes())
+        .nest("/matchmaking", matchmaking::routes())
+        .fallback(|| async { StatusCode::NOT_FOUND });
+    Router::new()
+        .nest("/api", api)
         .with_state(state)
-        .layer(TraceLayer::new_for_http());
-    axum::serve(listener, app).await.unwrap();
-    Ok(())
+        .fallback_service(client)
+        .layer(TraceLayer::new_for_http())
 }
 
 trait Ser: Serialize {
@@ -122,51 +341,134 @@ trait Ser: Serialize {
 }
 impl<T> Ser for T where T: Serialize {}
 
-async fn handle_socket(mut socket: WebSocket, tx: mpsc::Sender<Command>, user: Arc<User>) {
-    let (oneshot_tx, oneshot_rx) = oneshot::channel();
-
-    tx.send(Command::Join(user, oneshot_tx)).await.unwrap();
-    let res = oneshot_rx.await.unwrap();
-
-    let (self_id, mut room_rx) = match res {
-        Ok(t) => t,
-        Err(err) => {
-            socket
-                .send(Message::Close(Some(CloseFrame {
-                    code: 1011,
-                    reason: err.into(),
-                })))
-                .await
-                .unwrap();
-            return;
-        }
-    };
-
-    let (mut write, mut read) = socket.split();
-    loop {
-        select! {
-            Some(Ok(msg)) = read.next() => {
-                info!("Request: {msg:?}");
-                match msg {
-                    Message::Text(txt) => {
-                        let Ok(i) = serde_json::from_str::<Request>(&txt) else {break;};
-                        tx.send(match i {
-                            Request::SendMessage{content} => Command::SendMessage(self_id, content),
-                            Request::PlaySpecialCard(i, c) => Command::PlayCard(self_id, i, c),
-                            Request::TakeCard => Command::TakeCard(self_id),
-                        Request::PlayCards(cards) => Command::PlayCards(self_id, cards)
-                        }).await.unwrap();
-                    },
-                    Message::Close(_) => {
-                        break;
-                    }
-                    _ => ()
-                }
-            }
-            Some(msg) = room_rx.recv() => {
-                write.send(Message::Text(msg)).await.unwrap();
-            }
-        }
+#[cfg(test)]
+mod tests {
+    use tokio::sync::watch;
+
+    use super::*;
+
+    /// A lobby without a room, the returned sender stands in for it
+    fn lobby(name: &str) -> (Uuid, Lobby, watch::Sender<LobbyData>) {
+        let id = Uuid::new_v4();
+        let (data_tx, data) = watch::channel(LobbyData {
+            name: name.into(),
+            players: 0,
+            max_players: 4,
+            id,
+            game_started: false,
+            owner: String::new(),
+            locked: false,
+            rules: Rules::default(),
+            finished: false,
+        });
+        let lobby = Lobby {
+            tx: mpsc::channel(1).0,
+            owner: Uuid::nil(),
+            data,
+            private: false,
+            password: None,
+            code: String::new(),
+            table: false,
+        };
+        (id, lobby, data_tx)
+    }
+
+    #[test]
+    fn test_lobby_names_are_unique() {
+        let mut state = AppState::default();
+        let (id, first, _room) = lobby("office");
+        assert!(state.add_lobby(id, first));
+        let (other, second, _room) = lobby("office");
+        assert!(!state.add_lobby(other, second));
+        assert_eq!(state.free_lobby_name("office".into()), "office (2)");
+        state.remove_lobby(id).unwrap();
+        assert_eq!(state.free_lobby_name("office".into()), "office");
+    }
+
+    #[test]
+    fn test_invite_codes() {
+        let mut state = AppState::default();
+        let (id, first, _room) = lobby("office");
+        state.add_lobby(id, first);
+        let (other, second, _room) = lobby("lunch");
+        state.add_lobby(other, second);
+        let code = state.lobbies[&id].code.clone();
+        assert_eq!(code.len(), INVITE_CODE_LENGTH);
+        assert_ne!(code, state.lobbies[&other].code);
+        assert_eq!(state.lobby_codes[&code], id);
+        state.remove_lobby(id);
+        assert!(!state.lobby_codes.contains_key(&code));
+    }
+
+    #[test]
+    fn test_private_lobbies_are_hidden() {
+        let mut state = AppState::default();
+        let (id, mut private, _room) = lobby("secret");
+        private.private = true;
+        state.add_lobby(id, private);
+        let (other, mut locked, _room) = lobby("locked");
+        locked.password = Some("hunter2".into());
+        state.add_lobby(other, locked);
+        let data = state.lobby_data();
+        assert_eq!(data.len(), 1);
+        assert!(data[0].locked);
+    }
+
+    #[test]
+    fn test_lobby_events() {
+        let mut state = AppState::default();
+        let mut events = state.lobby_events.subscribe();
+        let (id, public, room) = lobby("office");
+        state.add_lobby(id, public);
+        let (private_id, mut private, _room) = lobby("secret");
+        private.private = true;
+        state.add_lobby(private_id, private);
+        room.send_modify(|d| d.players = 1);
+        state.lobby_updated(id, false);
+        state.lobby_updated(private_id, false);
+        state.remove_lobby(private_id);
+        state.remove_lobby(id);
+        assert!(matches!(events.try_recv(), Ok(LobbyEvent::Created(d)) if d.id == id));
+        assert!(matches!(events.try_recv(), Ok(LobbyEvent::Updated(d)) if d.players == 1));
+        assert!(
+            matches!(events.try_recv(), Ok(LobbyEvent::Removed { id: removed }) if removed == id)
+        );
+        assert!(events.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_finished_lobbies_are_hidden() {
+        let mut state = AppState::default();
+        let mut events = state.lobby_events.subscribe();
+        let (id, lobby, room) = lobby("office");
+        state.add_lobby(id, lobby);
+        room.send_modify(|d| d.finished = true);
+        state.lobby_updated(id, false);
+        assert!(state.lobby_data().is_empty());
+        // Still there for players rejoining it
+        assert!(state.lobbies.contains_key(&id));
+        room.send_modify(|d| d.finished = false);
+        state.lobby_updated(id, true);
+        assert_eq!(state.lobby_data().len(), 1);
+        assert!(matches!(events.try_recv(), Ok(LobbyEvent::Created(_))));
+        assert!(
+            matches!(events.try_recv(), Ok(LobbyEvent::Removed { id: removed }) if removed == id)
+        );
+        assert!(matches!(events.try_recv(), Ok(LobbyEvent::Created(d)) if d.id == id));
+    }
+
+    #[test]
+    fn test_lobby_data_drops_dead_rooms() {
+        let mut state = AppState::default();
+        let (id, alive, room) = lobby("alive");
+        state.add_lobby(id, alive);
+        let (dead_id, dead, dead_room) = lobby("dead");
+        state.add_lobby(dead_id, dead);
+        drop(dead_room);
+        room.send_modify(|d| d.players = 2);
+        let data = state.lobby_data();
+        assert_eq!(data.len(), 1);
+        assert_eq!(data[0].players, 2);
+        assert!(!state.lobby_names.contains_key("dead"));
     }
-    tx.send(Command::Leave(self_id)).await.unwrap();
 }
@@ -0,0 +1,401 @@
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket},
+        State, WebSocketUpgrade,
+    },
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{watch, Notify};
+use tracing::info;
+use ts_rs::TS;
+use uuid::Uuid;
+
+use crate::{
+    game::{DeckSpec, GameMode, Rules},
+    lobby::{invalid, FieldError, LobbyLimits, RuleChoices},
+    room::RoomActor,
+    shutdown::{self, Closing},
+    socket::{close, CLOSE_GOING_AWAY},
+    token_extractor::SessionToken,
+    user::User,
+    Ser, SharedState,
+};
+
+#[derive(Deserialize, Debug, TS)]
+#[ts(export)]
+struct QueueData {
+    /// How many players the game should have
+    players: usize,
+    /// Defaults to the classic deck
+    deck: Option<String>,
+    #[serde(default)]
+    seven_o: bool,
+    #[serde(default)]
+    jump_in: bool,
+    #[serde(default)]
+    mode: GameMode,
+}
+
+/// Pushed to the user's matchmaking socket
+#[derive(Serialize, Debug, TS)]
+#[serde(tag = "tag", content = "fields")]
+#[ts(export)]
+pub enum MatchmakingEvent {
+    /// A room was made for the user, join it like any other lobby
+    Matched { lobby: Uuid },
+}
+
+#[derive(Debug)]
+pub struct Ticket {
+    pub token: Uuid,
+    pub user: Arc<User>,
+    pub rules: Rules,
+}
+
+/// A group of tickets that want the same rules, enough of them to fill a room
+#[derive(Debug)]
+pub struct Group {
+    pub rules: Rules,
+    pub tickets: Vec<Ticket>,
+}
+
+pub struct Matchmaking {
+    /// Waiting users, oldest first
+    pub queue: Vec<Ticket>,
+    /// Wakes up the matchmaker when the queue changes
+    pub wakeup: Arc<Notify>,
+    /// Rooms made for users whose events socket hasn't picked them up yet, by user id
+    pub matches: HashMap<usize, Uuid>,
+    /// Bumped whenever a match is added, every events socket then checks for its user's
+    pub matched: watch::Sender<()>,
+}
+
+impl Default for Matchmaking {
+    fn default() -> Self {
+        Self {
+            queue: Vec::new(),
+            wakeup: Arc::new(Notify::new()),
+            matches: HashMap::new(),
+            matched: watch::Sender::new(()),
+        }
+    }
+}
+
+impl Matchmaking {
+    /// Puts the user in the queue, replacing their earlier ticket
+    pub fn enqueue(&mut self, ticket: Ticket) {
+        self.leave(ticket.user.id);
+        // A match that wasn't picked up yet was for an older ticket
+        self.matches.remove(&ticket.user.id);
+        self.queue.push(ticket);
+        self.wakeup.notify_one();
+    }
+
+    /// Keeps the room made for the user until their events socket sends it
+    pub fn matched(&mut self, user_id: usize, lobby: Uuid) {
+        self.matches.insert(user_id, lobby);
+        self.matched.send_replace(());
+    }
+
+    pub fn leave(&mut self, user_id: usize) -> bool {
+        let len = self.queue.len();
+        self.queue.retain(|t| t.user.id != user_id);
+        self.queue.len() != len
+    }
+
+    /// Takes every full group out of the queue, the users who waited longest go first
+    pub fn take_groups(&mut self) -> Vec<Group> {
+        let mut groups = Vec::new();
+        let mut i = 0;
+        while i < self.queue.len() {
+            let rules = &self.queue[i].rules;
+            let matching: Vec<usize> = (i..self.queue.len())
+                .filter(|&j| self.queue[j].rules == *rules)
+                .take(rules.max_players)
+                .collect();
+            if matching.len() < rules.max_players {
+                i += 1;
+                continue;
+            }
+            let rules = rules.clone();
+            // Remove from the back so the earlier indices stay valid
+            let mut tickets: Vec<Ticket> = matching
+                .into_iter()
+                .rev()
+                .map(|j| self.queue.remove(j))
+                .collect();
+            tickets.reverse();
+            groups.push(Group { rules, tickets });
+        }
+        groups
+    }
+}
+
+/// Spawns a room for every group of compatible users, runs for as long as the server does
+pub async fn run(state: SharedState) {
+    let wakeup = Arc::clone(&state.lock().matchmaking.wakeup);
+    loop {
+        wakeup.notified().await;
+        let mut guard = state.lock();
+        for group in guard.matchmaking.take_groups() {
+            let Some(deck) = guard.decks.get(&group.rules.deck).cloned() else {
+                continue;
+            };
+            let users = group.tickets.iter().map(|t| t.user.id).collect();
+            let name = guard.free_lobby_name(format!("Quick play {}", group.tickets[0].user.name));
+            let owner = group.tickets[0].token;
+            let (id, lobby) =
+                RoomActor::spawn_table(&state, owner, name, group.rules, deck, users, None);
+            info!("Matched {} players into {id}", group.tickets.len());
+            guard.add_lobby(id, lobby);
+            for ticket in &group.tickets {
+                guard.matchmaking.matched(ticket.user.id, id);
+            }
+        }
+    }
+}
+
+fn queue_rules(
+    input: QueueData,
+    limits: &LobbyLimits,
+    decks: &HashMap<String, DeckSpec>,
+) -> Result<Rules, Vec<FieldError>> {
+    let choices = RuleChoices {
+        players: input.players,
+        deck: input.deck,
+        seven_o: input.seven_o,
+        jump_in: input.jump_in,
+        mode: input.mode,
+        partner_exchange: false,
+    };
+    let mut errors = Vec::new();
+    match choices.validate("players", limits, decks, &mut errors) {
+        Some((rules, _)) => Ok(rules),
+        None => Err(errors),
+    }
+}
+
+async fn queue_join(
+    SessionToken(token): SessionToken,
+    State(state): State<SharedState>,
+    Json(input): Json<QueueData>,
+) -> Response {
+    let mut state = state.lock();
+    if state.shutting_down {
+        return (StatusCode::SERVICE_UNAVAILABLE, "Server is shutting down.").into_response();
+    }
+    let Some(user) = state.users.get(&token).map(Arc::clone) else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+    let rules = match queue_rules(input, &state.lobby_limits, &state.decks) {
+        Ok(rules) => rules,
+        Err(errors) => return invalid(errors),
+    };
+    state.matchmaking.enqueue(Ticket { token, user, rules });
+    StatusCode::ACCEPTED.into_response()
+}
+
+async fn queue_leave(
+    SessionToken(token): SessionToken,
+    State(state): State<SharedState>,
+) -> Response {
+    let mut state = state.lock();
+    let Some(user) = state.users.get(&token).map(Arc::clone) else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+    if state.matchmaking.leave(user.id) {
+        StatusCode::OK.into_response()
+    } else {
+        (StatusCode::NOT_FOUND, "Not in the queue").into_response()
+    }
+}
+
+/// Pushes the user's matches, including one made before it was opened. Closing it leaves the
+/// queue
+async fn queue_events(
+    SessionToken(token): SessionToken,
+    State(state): State<SharedState>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let (user, matched, closing) = {
+        let state = state.lock();
+        let Some(user) = state.users.get(&token).map(Arc::clone) else {
+            return StatusCode::UNAUTHORIZED.into_response();
+        };
+        let closing = state.closing.subscribe();
+        (user, state.matchmaking.matched.subscribe(), closing)
+    };
+    ws.on_upgrade(move |socket| async move {
+        handle_events(socket, &state, user.id, matched, closing).await;
+        state.lock().matchmaking.leave(user.id);
+    })
+}
+
+async fn handle_events(
+    mut socket: WebSocket,
+    state: &SharedState,
+    user_id: usize,
+    mut matched: watch::Receiver<()>,
+    mut closing: Closing,
+) {
+    loop {
+        // Checked after subscribing, so a match made in between still wakes the loop up
+        let lobby = state.lock().matchmaking.matches.remove(&user_id);
+        if let Some(lobby) = lobby {
+            let event = MatchmakingEvent::Matched { lobby }.ser();
+            if socket.send(Message::Text(event)).await.is_err() {
+                // Keep it for the next socket, unless a newer match came in
+                state
+                    .lock()
+                    .matchmaking
+                    .matches
+                    .entry(user_id)
+                    .or_insert(lobby);
+                return;
+            }
+        }
+        tokio::select! {
+            changed = matched.changed() => {
+                if changed.is_err() {
+                    return;
+                }
+            },
+            msg = socket.recv() => match msg {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                Some(Ok(_)) => (),
+            },
+            _ = shutdown::closing(&mut closing) => {
+                close(&mut socket, CLOSE_GOING_AWAY, "Server is shutting down").await;
+                return;
+            }
+        }
+    }
+}
+
+pub fn routes() -> Router<SharedState> {
+    Router::new()
+        .route("/queue", post(queue_join).delete(queue_leave))
+        .route("/events", get(queue_events))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ticket(id: usize, rules: &Rules) -> Ticket {
+        Ticket {
+            token: Uuid::new_v4(),
+            user: Arc::new(User {
+                id,
+                ..User::new_empty()
+            }),
+            rules: rules.clone(),
+        }
+    }
+
+    fn rules(players: usize) -> Rules {
+        Rules {
+            max_players: players,
+            ..Default::default()
+        }
+    }
+
+    fn ids(group: &Group) -> Vec<usize> {
+        group.tickets.iter().map(|t| t.user.id).collect()
+    }
+
+    #[test]
+    fn test_groups_need_enough_players() {
+        let mut matchmaking = Matchmaking::default();
+        matchmaking.enqueue(ticket(0, &rules(3)));
+        matchmaking.enqueue(ticket(1, &rules(3)));
+        assert!(matchmaking.take_groups().is_empty());
+        matchmaking.enqueue(ticket(2, &rules(3)));
+        let groups = matchmaking.take_groups();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(ids(&groups[0]), vec![0, 1, 2]);
+        assert!(matchmaking.queue.is_empty());
+    }
+
+    #[test]
+    fn test_groups_only_match_same_rules() {
+        let mut matchmaking = Matchmaking::default();
+        let seven_o = Rules {
+            seven_o: true,
+            ..rules(2)
+        };
+        matchmaking.enqueue(ticket(0, &rules(2)));
+        matchmaking.enqueue(ticket(1, &seven_o));
+        matchmaking.enqueue(ticket(2, &rules(3)));
+        assert!(matchmaking.take_groups().is_empty());
+        matchmaking.enqueue(ticket(3, &seven_o));
+        matchmaking.enqueue(ticket(4, &rules(2)));
+        let groups = matchmaking.take_groups();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(ids(&groups[0]), vec![0, 4]);
+        assert_eq!(ids(&groups[1]), vec![1, 3]);
+        assert_eq!(matchmaking.queue[0].user.id, 2);
+    }
+
+    #[test]
+    fn test_longest_waiting_go_first() {
+        let mut matchmaking = Matchmaking::default();
+        for id in 0..5 {
+            matchmaking.enqueue(ticket(id, &rules(2)));
+        }
+        let groups = matchmaking.take_groups();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(ids(&groups[0]), vec![0, 1]);
+        assert_eq!(ids(&groups[1]), vec![2, 3]);
+        assert_eq!(matchmaking.queue.len(), 1);
+    }
+
+    #[test]
+    fn test_requeue_replaces_ticket() {
+        let mut matchmaking = Matchmaking::default();
+        matchmaking.enqueue(ticket(0, &rules(2)));
+        matchmaking.enqueue(ticket(0, &rules(2)));
+        assert_eq!(matchmaking.queue.len(), 1);
+        assert!(matchmaking.take_groups().is_empty());
+        assert!(matchmaking.leave(0));
+        assert!(!matchmaking.leave(0));
+    }
+
+    #[test]
+    fn test_matches_wait_for_their_user() {
+        let mut matchmaking = Matchmaking::default();
+        let matched = matchmaking.matched.subscribe();
+        let lobby = Uuid::new_v4();
+        matchmaking.matched(0, lobby);
+        assert!(matched.has_changed().unwrap());
+        assert_eq!(matchmaking.matches.get(&0), Some(&lobby));
+        // Queueing again drops the match nobody picked up
+        matchmaking.enqueue(ticket(0, &rules(2)));
+        assert!(matchmaking.matches.is_empty());
+    }
+
+    #[test]
+    fn test_queue_rules_errors_name_the_field() {
+        let decks = DeckSpec::presets().into_iter().collect();
+        let input = QueueData {
+            players: 5,
+            deck: Some("nope".into()),
+            seven_o: false,
+            jump_in: false,
+            mode: GameMode::Teams,
+        };
+        assert_eq!(
+            queue_rules(input, &LobbyLimits::default(), &decks).unwrap_err(),
+            vec![
+                FieldError::new("players", "Teams need 4 or 6 players."),
+                FieldError::new("deck", "Unknown deck."),
+            ]
+        );
+    }
+}
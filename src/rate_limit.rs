@@ -0,0 +1,193 @@
+use std::{collections::HashMap, hash::Hash, time::Instant};
+
+use serde::Deserialize;
+
+/// Once a keyed limiter tracks this many buckets, full ones get dropped
+const PRUNE_THRESHOLD: usize = 1024;
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimit {
+    /// How many requests can be made in a burst
+    pub burst: u32,
+    /// How many requests are allowed per second after the burst is spent
+    pub per_second: f64,
+}
+
+/// Limits for everything clients can spam
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimits {
+    /// Requests over a game socket
+    pub socket: RateLimit,
+    /// `POST /user/login`, per IP address
+    pub login: RateLimit,
+    /// `POST /lobbies`, per session
+    pub create_lobby: RateLimit,
+    /// Wrong lobby passwords, per IP address
+    pub password: RateLimit,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self {
+            socket: RateLimit {
+                burst: 10,
+                per_second: 5.0,
+            },
+            login: RateLimit {
+                burst: 5,
+                per_second: 0.1,
+            },
+            create_lobby: RateLimit {
+                burst: 3,
+                per_second: 0.2,
+            },
+            password: RateLimit {
+                burst: 5,
+                per_second: 0.1,
+            },
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct TokenBucket {
+    limit: RateLimit,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            tokens: limit.burst as f64,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Takes a token if one is available, returns false if the caller should be throttled
+    pub fn try_take(&mut self) -> bool {
+        self.try_take_at(Instant::now())
+    }
+
+    fn try_take_at(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns a token taken for a request that turned out not to count
+    fn put_back(&mut self) {
+        self.tokens = (self.tokens + 1.0).min(self.limit.burst as f64);
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.limit.per_second)
+            .min(self.limit.burst as f64);
+        self.last_refill = now;
+    }
+
+    fn is_full(&self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        self.tokens + elapsed.as_secs_f64() * self.limit.per_second >= self.limit.burst as f64
+    }
+}
+
+/// A token bucket per key, e.g. per user or per IP address
+#[derive(Debug)]
+pub struct KeyedRateLimiter<K> {
+    limit: RateLimit,
+    buckets: HashMap<K, TokenBucket>,
+}
+
+impl<K: Hash + Eq> KeyedRateLimiter<K> {
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            buckets: HashMap::new(),
+        }
+    }
+
+    /// Takes a token from `key`'s bucket, returns false if `key` should be throttled
+    pub fn try_take(&mut self, key: K) -> bool {
+        let now = Instant::now();
+        if self.buckets.len() >= PRUNE_THRESHOLD {
+            self.buckets.retain(|_, bucket| !bucket.is_full(now));
+        }
+        self.buckets
+            .entry(key)
+            .or_insert_with(|| TokenBucket::new(self.limit))
+            .try_take_at(now)
+    }
+
+    /// Returns a token taken from `key`'s bucket, for requests that turned out not to count
+    pub fn put_back(&mut self, key: &K) {
+        if let Some(bucket) = self.buckets.get_mut(key) {
+            bucket.put_back();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    const LIMIT: RateLimit = RateLimit {
+        burst: 3,
+        per_second: 2.0,
+    };
+
+    #[test]
+    fn test_bucket_burst_then_throttle() {
+        let mut bucket = TokenBucket::new(LIMIT);
+        let now = bucket.last_refill;
+        assert!((0..3).all(|_| bucket.try_take_at(now)));
+        assert!(!bucket.try_take_at(now));
+    }
+
+    #[test]
+    fn test_bucket_refills() {
+        let mut bucket = TokenBucket::new(LIMIT);
+        let now = bucket.last_refill;
+        while bucket.try_take_at(now) {}
+        assert!(!bucket.try_take_at(now + Duration::from_millis(400)));
+        assert!(bucket.try_take_at(now + Duration::from_millis(600)));
+        // Never refills past the burst size
+        let later = now + Duration::from_secs(60);
+        assert!((0..3).all(|_| bucket.try_take_at(later)));
+        assert!(!bucket.try_take_at(later));
+    }
+
+    #[test]
+    fn test_keyed_limiter_separates_keys() {
+        let mut limiter = KeyedRateLimiter::new(LIMIT);
+        assert!((0..3).all(|_| limiter.try_take("a")));
+        assert!(!limiter.try_take("a"));
+        assert!(limiter.try_take("b"));
+    }
+
+    #[test]
+    fn test_keyed_limiter_put_back() {
+        let mut limiter = KeyedRateLimiter::new(LIMIT);
+        assert!((0..3).all(|_| limiter.try_take("a")));
+        limiter.put_back(&"a");
+        assert!(limiter.try_take("a"));
+        assert!(!limiter.try_take("a"));
+        // Never more than the burst size
+        let mut limiter = KeyedRateLimiter::new(LIMIT);
+        limiter.try_take("b");
+        limiter.put_back(&"b");
+        limiter.put_back(&"b");
+        assert!((0..3).all(|_| limiter.try_take("b")));
+        assert!(!limiter.try_take("b"));
+    }
+}
@@ -1,49 +1,312 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use futures_util::future::join_all;
 use indexmap::IndexMap;
-use rand::seq::SliceRandom;
-use tokio::sync::mpsc;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    select,
+    sync::{mpsc, oneshot, watch},
+    time::{sleep_until, Instant},
+};
 use tracing::{error, info};
 use uuid::Uuid;
 
 use crate::{
-    game::{CardKind, Color, NormalCardKind, Player, State},
+    game::{Action, DeckSpec, Game, GameError, GameEvent, GameMode, Rules},
     game_messages::{ChatMessage, GameState, PlayerInfo, Response},
+    lobby::Lobby,
     user::User,
-    Command, LobbyData, PlayerId, Ser,
+    Command, JoinResult, LobbyData, PlayerId, Ser, SharedState,
 };
-pub const MAX_CARD_HISTORY: usize = 8;
+#[derive(Clone, Copy, Debug)]
+pub struct RoomSettings {
+    /// Rooms close when nobody has played or chatted for this long
+    pub idle: Duration,
+    /// Rooms nobody is connected to close after this long, reserved rooms only use `idle`
+    pub empty: Duration,
+    /// How many of the last played cards are sent with the game state
+    pub card_history: usize,
+    /// How long a disconnected player's turn waits for them before it's passed
+    pub reconnect: Duration,
+}
+
+impl Default for RoomSettings {
+    fn default() -> Self {
+        Self {
+            idle: Duration::from_secs(10 * 60),
+            empty: Duration::from_secs(30),
+            card_history: 8,
+            reconnect: Duration::from_secs(60),
+        }
+    }
+}
+
+/// A player's connection, seats are in the same order as the game's hands
+#[derive(Debug)]
+pub struct Seat {
+    pub tx: mpsc::Sender<String>,
+    pub user: Arc<User>,
+    /// False while the player's socket is gone, their seat is kept so they can reconnect
+    pub connected: bool,
+    /// When the player last disconnected, only meaningful while they're away
+    pub left_at: Instant,
+    /// Round trip time of the last heartbeat
+    pub latency: Option<Duration>,
+}
+impl Seat {
+    pub fn new(user: Arc<User>, tx: mpsc::Sender<String>) -> Self {
+        Self {
+            tx,
+            user,
+            connected: true,
+            left_at: Instant::now(),
+            latency: None,
+        }
+    }
+    /// A seat waiting for its player to reconnect, messages to it go nowhere
+    fn disconnected(user: Arc<User>) -> Self {
+        Self {
+            connected: false,
+            ..Self::new(user, mpsc::channel(1).0)
+        }
+    }
+}
+
+/// What a room needs to carry on after a restart, without the connections
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RoomState {
+    pub name: String,
+    pub game: Game,
+    /// Seated users in seat order, they get their seats back by joining again
+    pub seats: Vec<User>,
+    pub reserved: Option<Vec<usize>>,
+}
+
 pub struct RoomActor {
     name: String,
-    game_started: bool,
-    pub players: IndexMap<PlayerId, Player>,
-    max_players: usize,
+    pub players: IndexMap<PlayerId, Seat>,
+    game: Game,
     next_id: usize,
     id: Uuid,
     rx: mpsc::Receiver<Command>,
-    cards_played: usize,
+    /// Events since the last game state broadcast
+    events: Vec<GameEvent>,
+    /// What the lobby list shows, updated whenever it changes
+    data: watch::Sender<LobbyData>,
+    /// Ids of the only users allowed to sit down, the game starts once they all have
+    reserved: Option<Vec<usize>>,
+    /// Gets the winner's user id when the game ends
+    on_finish: Option<oneshot::Sender<usize>>,
+    /// The room takes itself out of the lobbies when it closes or its game ends
+    app: SharedState,
+    settings: RoomSettings,
+    last_activity: Instant,
+    /// When the last action went through, a disconnected player's turn is passed
+    /// `settings.reconnect` after this or after they left, whichever is later
+    turn_started: Instant,
+    /// Restored from a snapshot and nobody has reconnected yet, the room doesn't count as empty
+    restored: bool,
 }
 impl RoomActor {
-    pub fn spawn_new(name: String, max_players: usize) -> (mpsc::Sender<Command>, Uuid) {
+    /// Spawns the room, the returned lobby still has to be added to the app state
+    pub fn spawn_new(
+        app: &SharedState,
+        owner: Uuid,
+        name: String,
+        rules: Rules,
+        deck: DeckSpec,
+    ) -> (Uuid, Lobby) {
+        let (room, tx) = Self::new(app, name, rules, deck);
+        room.spawn(tx, owner)
+    }
+
+    /// Spawns a room only the given users can sit in, the winner is sent to `on_finish`
+    pub fn spawn_table(
+        app: &SharedState,
+        owner: Uuid,
+        name: String,
+        rules: Rules,
+        deck: DeckSpec,
+        users: Vec<usize>,
+        on_finish: Option<oneshot::Sender<usize>>,
+    ) -> (Uuid, Lobby) {
+        let (mut room, tx) = Self::new(app, name, rules, deck);
+        room.reserved = Some(users);
+        room.on_finish = on_finish;
+        room.spawn(tx, owner)
+    }
+
+    /// Spawns a room from a snapshot under its old id, every seat waits for its player
+    pub fn spawn_restored(
+        app: &SharedState,
+        id: Uuid,
+        owner: Uuid,
+        state: RoomState,
+    ) -> (Uuid, Lobby) {
+        let (mut room, tx) = Self::with_game(app, id, state.name, state.game);
+        room.reserved = state.reserved;
+        for user in state.seats {
+            room.players
+                .insert(room.next_id, Seat::disconnected(Arc::new(user)));
+            room.next_id += 1;
+        }
+        room.restored = true;
+        room.data.send_replace(room.lobby_data());
+        room.spawn(tx, owner)
+    }
+
+    fn spawn(self, tx: mpsc::Sender<Command>, owner: Uuid) -> (Uuid, Lobby) {
+        let id = self.id;
+        let data = self.data.subscribe();
+        let table = self.reserved.is_some();
+        tokio::spawn(self.run());
+        let lobby = Lobby {
+            tx,
+            owner,
+            data,
+            private: false,
+            password: None,
+            code: String::new(),
+            table,
+        };
+        (id, lobby)
+    }
+
+    fn new(
+        app: &SharedState,
+        name: String,
+        rules: Rules,
+        deck: DeckSpec,
+    ) -> (Self, mpsc::Sender<Command>) {
+        Self::with_game(app, Uuid::new_v4(), name, Game::new(rules, &deck))
+    }
+
+    fn with_game(
+        app: &SharedState,
+        id: Uuid,
+        name: String,
+        game: Game,
+    ) -> (Self, mpsc::Sender<Command>) {
         let (tx, rx) = mpsc::channel(8);
-        let id = Uuid::new_v4();
+        let data = LobbyData {
+            name: name.clone(),
+            players: 0,
+            max_players: game.rules.max_players,
+            id,
+            game_started: game.started,
+            owner: String::new(),
+            locked: false,
+            rules: game.rules.clone(),
+            finished: false,
+        };
         let room = Self {
             name,
-            game_started: false,
             next_id: 0,
             rx,
             id,
             players: IndexMap::new(),
-            max_players,
-            cards_played: 0,
+            game,
+            events: Vec::new(),
+            data: watch::Sender::new(data),
+            reserved: None,
+            on_finish: None,
+            app: SharedState::clone(app),
+            settings: RoomSettings::default(),
+            last_activity: Instant::now(),
+            turn_started: Instant::now(),
+            restored: false,
         };
-        tokio::spawn(room.run());
-        (tx, id)
+        (room, tx)
+    }
+
+    fn lobby_data(&self) -> LobbyData {
+        LobbyData {
+            name: self.name.clone(),
+            players: self.players.len(),
+            max_players: self.game.rules.max_players,
+            id: self.id,
+            game_started: self.game.started,
+            owner: String::new(),
+            locked: false,
+            rules: self.game.rules.clone(),
+            finished: self.finished(),
+        }
+    }
+
+    /// Elimination games last until one player is left, otherwise every round is a game of its
+    /// own
+    fn finished(&self) -> bool {
+        self.game.game_over
+            || (self.game.round_over && self.game.rules.mode != GameMode::Elimination)
+    }
+
+    /// Updates the lobby list's copy of the room if anything in it changed
+    fn publish(&self) {
+        let data = self.lobby_data();
+        let mut was_finished = false;
+        let changed = self.data.send_if_modified(|old| {
+            was_finished = old.finished;
+            let changed = *old != data;
+            *old = data;
+            changed
+        });
+        if changed {
+            self.app.lock().lobby_updated(self.id, was_finished);
+        }
+    }
+
+    /// When the room closes if nothing happens before then
+    fn deadline(&self) -> Instant {
+        let empty = !self.players.values().any(|p| p.connected);
+        if empty && self.reserved.is_none() && !self.restored {
+            self.last_activity + self.settings.empty
+        } else {
+            self.last_activity + self.settings.idle
+        }
+    }
+
+    /// When to pass the turn of the current player if they're disconnected. Nobody is passed
+    /// while everyone is away, there'd be no one to play against
+    fn pass_deadline(&self) -> Option<Instant> {
+        if !self.game.started || self.game.round_over || self.game.game_over {
+            return None;
+        }
+        let (_, seat) = self.players.get_index(self.game.state.turn_index)?;
+        if seat.connected || !self.players.values().any(|p| p.connected) {
+            return None;
+        }
+        Some(self.turn_started.max(seat.left_at) + self.settings.reconnect)
+    }
+
+    /// The room's game if it's in progress. Tournament tables are left out, the tournament
+    /// isn't kept
+    fn snapshot(&self) -> Option<RoomState> {
+        if !self.game.started || self.game.game_over || self.on_finish.is_some() {
+            return None;
+        }
+        Some(RoomState {
+            name: self.name.clone(),
+            game: self.game.clone(),
+            seats: self
+                .players
+                .values()
+                .map(|p| User::clone(&p.user))
+                .collect(),
+            reserved: self.reserved.clone(),
+        })
+    }
+
+    /// Stops listing the room, players still in it can stay until they leave
+    fn delist(&self) {
+        self.app.lock().remove_lobby(self.id);
     }
 
     async fn broadcast_message(&self, message: ChatMessage<'_>) {
-        let data = Response::ChatMessage(message).ser();
+        self.broadcast(Response::ChatMessage(message)).await;
+    }
+
+    async fn broadcast(&self, response: Response<'_>) {
+        let data = response.ser();
         join_all(
             self.players
                 .values()
@@ -52,30 +315,42 @@ impl RoomActor {
         .await;
     }
 
-    async fn broadcast_gamestate(&self, game_state: &State) {
-        let top_card = game_state.played_cards.last();
+    async fn broadcast_gamestate(&mut self) {
+        let events = std::mem::take(&mut self.events);
+        let game = &self.game;
+        let top_card = game.state.played_cards.last();
         let player_data: Vec<PlayerInfo> = self
             .players
             .values()
-            .map(|p| PlayerInfo {
-                user: &p.user,
+            .zip(&game.players)
+            .map(|(s, p)| PlayerInfo {
+                user: &s.user,
                 card_count: p.cards.len(),
+                connected: s.connected,
+                latency_ms: s.latency.map(|l| l.as_millis() as u32),
+                team: p.team,
+                eliminated: p.eliminated,
             })
             .collect();
-        for (i, p) in self.players.values().enumerate() {
-            p.tx.send(
+        for (i, (s, p)) in self.players.values().zip(&game.players).enumerate() {
+            s.tx.send(
                 Response::GameState(GameState {
                     users: &player_data,
                     own_cards: &p.cards,
-                    turn_index: game_state.turn_index,
+                    turn_index: game.state.turn_index,
                     top_card,
                     self_index: i,
-                    direction: game_state.turn_direction,
-                    cards_played: self.cards_played,
-                    last_played_cards: &game_state.played_cards[game_state
+                    direction: game.state.turn_direction,
+                    cards_played: game.cards_played,
+                    last_played_cards: &game.state.played_cards[game
+                        .state
                         .played_cards
                         .len()
-                        .saturating_sub(MAX_CARD_HISTORY)..],
+                        .saturating_sub(self.settings.card_history)..],
+                    awaiting_swap_target: game.awaiting_swap_target,
+                    round_over: game.round_over,
+                    game_over: game.game_over,
+                    events: &events,
                 })
                 .ser(),
             )
@@ -84,148 +359,229 @@ impl RoomActor {
         }
     }
     pub async fn run(mut self) {
-        let mut game_state = State::default();
-        game_state.unplayed_cards.shuffle(&mut rand::thread_rng());
-        while let Some(cmd) = self.rx.recv().await {
-            match cmd {
-                Command::GetData(sender) => {
-                    sender
-                        .send(LobbyData {
-                            name: self.name.clone(),
-                            players: self.players.len(),
-                            max_players: self.max_players,
-                            id: self.id,
-                        })
-                        .unwrap();
+        // Read here because whoever spawns the room may still hold the lock
+        self.settings = self.app.lock().room_settings;
+        loop {
+            let deadline = self.deadline();
+            let pass_at = self.pass_deadline();
+            let cmd = select! {
+                cmd = self.rx.recv() => cmd,
+                _ = sleep_until(deadline) => {
+                    info!("Room {} timed out", self.id);
+                    break;
+                }
+                _ = sleep_until(pass_at.unwrap_or(deadline)), if pass_at.is_some() => {
+                    self.pass_turn().await;
+                    self.publish();
+                    continue;
                 }
+            };
+            let Some(cmd) = cmd else { break };
+            if !matches!(
+                cmd,
+                Command::Latency(..)
+                    | Command::ServerShutdown(_)
+                    | Command::Snapshot(_)
+                    | Command::Noop
+            ) {
+                self.last_activity = Instant::now();
+            }
+            match cmd {
                 Command::Join(user_name, sender) => {
-                    self.handle_join(sender, &game_state, user_name).await;
+                    self.handle_join(sender, user_name).await;
                 }
                 Command::SendMessage(user_id, content) => {
-                    self.handle_send_message(content, &mut game_state, user_id)
-                        .await;
+                    self.handle_send_message(content, user_id).await;
                 }
-                Command::PlayCard(user_id, i, c) => {
-                    self.handle_play_special_card(&mut game_state, user_id, i, c)
+                Command::PlayCard(user_id, card, color) => {
+                    self.handle_action(user_id, |player| Action::PlaySpecialCard {
+                        player,
+                        card,
+                        color,
+                    })
+                    .await;
+                }
+                Command::PlayTargetedCard(user_id, card, color, target) => {
+                    self.handle_action(user_id, |player| Action::PlayTargetedCard {
+                        player,
+                        card,
+                        color,
+                        target,
+                    })
+                    .await;
+                }
+                Command::JumpIn(user_id, card, cards_played) => {
+                    self.handle_action(user_id, |player| Action::JumpIn {
+                        player,
+                        card,
+                        cards_played,
+                    })
+                    .await;
+                }
+                Command::ChooseSwapTarget(user_id, target) => {
+                    self.handle_action(user_id, |player| Action::ChooseSwapTarget {
+                        player,
+                        target,
+                    })
+                    .await;
+                }
+                Command::GiveToPartner(user_id, card) => {
+                    self.handle_action(user_id, |player| Action::GiveToPartner { player, card })
                         .await;
                 }
                 Command::Leave(user_id) => {
-                    if let Some(p) = self.players.shift_remove(&user_id) {
-                        game_state.unplayed_cards.extend(p.cards.into_iter())
+                    self.handle_leave(user_id).await;
+                }
+                Command::Latency(user_id, latency) => {
+                    if let Some(p) = self.players.get_mut(&user_id) {
+                        p.latency = Some(latency);
                     }
                 }
                 Command::TakeCard(user_id) => {
-                    self.handle_take_card(&user_id, &mut game_state).await
+                    self.handle_action(user_id, |player| Action::TakeCard { player })
+                        .await;
+                }
+                Command::PlayCards(user_id, cards) => {
+                    self.handle_action(user_id, |player| Action::PlayCards { player, cards })
+                        .await;
                 }
-                Command::PlayCards(user_id, cards_ids) => {
-                    self.handle_play_cards(&user_id, &mut game_state, cards_ids)
+                Command::ServerShutdown(grace) => {
+                    let in_seconds = grace.as_secs().try_into().unwrap_or(u32::MAX);
+                    self.broadcast(Response::ServerShutdown { in_seconds })
                         .await;
                 }
+                Command::Snapshot(sender) => {
+                    sender.send(self.snapshot()).ok();
+                }
                 Command::Shutdown => break,
                 Command::Noop => (),
             };
+            self.publish();
         }
+        // Dropping the room hangs up on everyone still connected
+        self.delist();
     }
-    async fn handle_take_card(&mut self, player_id: &PlayerId, game_state: &mut State) {
-        if let Some(p) = self
-            .get_mut_player_if_turn(&player_id, &game_state)
-            .filter(|p| !p.can_play_card(&game_state))
-        {
-            p.cards.push(game_state.draw_card());
-            game_state.next_turn(self);
-            self.broadcast_gamestate(&game_state).await;
+
+    /// Applies the player's action to the game, errors are only sent to that player
+    async fn handle_action(&mut self, user_id: PlayerId, action: impl FnOnce(usize) -> Action) {
+        let Some(index) = self.players.get_index_of(&user_id) else {
+            return;
+        };
+        match self.game.apply(action(index)) {
+            Ok(events) => self.applied(events).await,
+            Err(e) => self.send_error(user_id, e).await,
         }
     }
-    fn get_mut_player_if_turn(
-        &mut self,
-        player_id: &PlayerId,
-        game_state: &State,
-    ) -> Option<&mut Player> {
-        self.players
-            .get_index_mut(game_state.turn_index)
-            .and_then(|(id, p)| (id == player_id).then_some(p))
+
+    /// Passes the turn of a player who didn't reconnect in time
+    async fn pass_turn(&mut self) {
+        let player = self.game.state.turn_index;
+        if let Some((_, seat)) = self.players.get_index(player) {
+            info!("Passing {}'s turn in room {}", seat.user.name, self.id);
+        }
+        // Restarts the wait even if the pass fails, so it isn't retried right away
+        self.turn_started = Instant::now();
+        match self.game.apply(Action::Pass { player }) {
+            Ok(events) => self.applied(events).await,
+            Err(e) => error!("Couldn't pass player {player}'s turn: {e}"),
+        }
     }
-    async fn handle_play_special_card(
-        &mut self,
-        game_state: &mut State,
-        user_id: usize,
-        card_index: usize,
-        new_color: Color,
-    ) {
-        let Some(player) = self.get_mut_player_if_turn(&user_id, &game_state) else {
+
+    async fn applied(&mut self, events: Vec<GameEvent>) {
+        self.turn_started = Instant::now();
+        self.report_winner(&events);
+        self.events.extend(events);
+        self.broadcast_gamestate().await;
+    }
+
+    /// Sends the winner to `on_finish` once the game is decided. Elimination games last until
+    /// one player is left, otherwise the first round decides it
+    fn report_winner(&mut self, events: &[GameEvent]) {
+        let winner = events.iter().find_map(|e| match *e {
+            GameEvent::GameOver { winner } => Some(winner),
+            GameEvent::RoundOver { winner, .. }
+                if self.game.rules.mode != GameMode::Elimination =>
+            {
+                Some(winner)
+            }
+            _ => None,
+        });
+        let Some((_, seat)) = winner.and_then(|w| self.players.get_index(w)) else {
             return;
         };
-        if player
-            .cards
-            .get(card_index)
-            .is_some_and(|c| matches!(c.kind, CardKind::Special(_)))
-        {
-            let mut card = player.cards.remove(card_index);
-            card.color = new_color;
-            game_state.place_card(card.clone());
-            game_state.next_turn(self);
-
-            self.cards_played += 1;
-            self.broadcast_gamestate(game_state).await;
+        if let Some(on_finish) = self.on_finish.take() {
+            on_finish.send(seat.user.id).ok();
+            self.delist();
         }
     }
-    async fn start(&mut self, game_state: &mut State) {
-        info!("STARTED");
-        for p in self.players.values_mut() {
-            p.cards = game_state
-                .unplayed_cards
-                .split_off(game_state.unplayed_cards.len() - 7);
-            info!("{} got cards: {:?}", p.user.name, p.cards);
+
+    async fn send_error(&self, user_id: PlayerId, error: GameError) {
+        if let Some(p) = self.players.get(&user_id) {
+            p.tx.send(Response::Error(error.to_string()).ser())
+                .await
+                .ok();
         }
-        self.game_started = true;
-        let index = game_state
-            .unplayed_cards
-            .iter()
-            .rposition(|card| matches!(card.kind, CardKind::Normal(NormalCardKind::Number(_))))
-            .unwrap();
-        let top_card = game_state.unplayed_cards.remove(index);
-        game_state.place_card(top_card);
-        self.cards_played = 1;
-        self.broadcast_gamestate(game_state).await;
     }
-    async fn handle_send_message(
-        &mut self,
-        content: String,
-        game_state: &mut State,
-        user_id: usize,
-    ) {
-        if !self.game_started && content.trim() == "/start" {
-            self.start(game_state).await;
+
+    async fn handle_send_message(&mut self, content: String, user_id: usize) {
+        if content.trim() == "/start" {
+            info!("STARTED");
+            self.handle_action(user_id, |_| Action::Start).await;
+        } else if let Some(team) = content.trim().strip_prefix("/team ") {
+            // Teams are shown starting from 1
+            match team.trim().parse::<usize>() {
+                Ok(team) if team > 0 => {
+                    self.handle_action(user_id, |player| Action::SetTeam {
+                        player,
+                        team: team - 1,
+                    })
+                    .await
+                }
+                _ => self.send_error(user_id, GameError::InvalidTeam).await,
+            }
         }
+        let Some(player) = self.players.get(&user_id) else {
+            return;
+        };
         self.broadcast_message(ChatMessage {
             content: &content,
-            user_name: &(self.players.get(&user_id).unwrap().user).name,
+            user_name: &player.user.name,
         })
         .await;
     }
 
     async fn handle_join(
         &mut self,
-        sender: tokio::sync::oneshot::Sender<Result<(usize, mpsc::Receiver<String>), String>>,
-        game_state: &State,
+        sender: tokio::sync::oneshot::Sender<JoinResult>,
         user: Arc<User>,
     ) {
-        if self.game_started {
-            sender.send(Err("Already started".into())).unwrap();
-        } else if self.players.len() >= self.max_players {
-            sender.send(Err("Room is full".into())).unwrap();
+        if self.game.started {
+            self.handle_rejoin(sender, user).await;
+        } else if self.players.len() >= self.game.rules.max_players {
+            sender.send(Err("Room is full".into())).ok();
+        } else if self.reserved.as_ref().is_some_and(|r| {
+            !r.contains(&user.id) || self.players.values().any(|p| p.user.id == user.id)
+        }) {
+            sender.send(Err("This table is reserved".into())).ok();
         } else {
+            // The engine has to know the player before the joiner is told it has a seat
+            let index = match self.game.add_player() {
+                Ok(index) => index,
+                Err(e) => {
+                    sender.send(Err(e.to_string())).ok();
+                    return;
+                }
+            };
             let (tx, rx) = mpsc::channel(1);
-            sender.send(Ok((self.next_id, rx))).unwrap();
-
-            self.players.insert(
-                self.next_id,
-                Player {
-                    cards: Vec::new(),
-                    tx,
-                    user: Arc::clone(&user),
-                },
-            );
+            if sender
+                .send(Ok((self.next_id, rx, self.game.rules.clone())))
+                .is_err()
+            {
+                self.game.remove_player(index).ok();
+                return;
+            }
+            self.players
+                .insert(self.next_id, Seat::new(Arc::clone(&user), tx));
             self.next_id += 1;
 
             self.broadcast_message(ChatMessage {
@@ -233,40 +589,134 @@ impl RoomActor {
                     "{} joined! {}/{} players.",
                     &user.name,
                     self.players.len(),
-                    self.max_players
+                    self.game.rules.max_players
                 ),
-                user_name: "SERVER".into(),
+                user_name: "SERVER",
             })
             .await;
-            self.broadcast_gamestate(game_state).await;
+            self.broadcast_gamestate().await;
+            if self
+                .reserved
+                .as_ref()
+                .is_some_and(|r| r.len() == self.players.len())
+            {
+                self.handle_action(self.next_id - 1, |_| Action::Start)
+                    .await;
+            }
         };
     }
 
-    async fn handle_play_cards(
+    /// Gives a disconnected player's seat back to them
+    async fn handle_rejoin(
         &mut self,
-        user_id: &usize,
-        game_state: &mut State,
-        card_indeces: Vec<usize>,
+        sender: tokio::sync::oneshot::Sender<JoinResult>,
+        user: Arc<User>,
     ) {
-        let Some(player) = self.get_mut_player_if_turn(user_id, game_state) else {
+        let Some((&id, player)) = self
+            .players
+            .iter_mut()
+            .find(|(_, p)| !p.connected && p.user.id == user.id)
+        else {
+            sender.send(Err("Already started".into())).ok();
             return;
         };
-        if !player.can_play_consecutive_cards(game_state, &card_indeces) {
+        let (tx, rx) = mpsc::channel(1);
+        if sender.send(Ok((id, rx, self.game.rules.clone()))).is_err() {
             return;
         }
-        // The player can actually play all the cards in cards_ids
-        let new_cards = Vec::with_capacity(player.cards.len() - card_indeces.len());
-        let player_cards = std::mem::replace(&mut player.cards, new_cards);
-        for i in &card_indeces {
-            game_state.place_card(player_cards[*i].clone())
+        player.tx = tx;
+        player.connected = true;
+        self.restored = false;
+        self.broadcast_message(ChatMessage {
+            content: &format!("{} reconnected!", &user.name),
+            user_name: "SERVER",
+        })
+        .await;
+        self.broadcast_gamestate().await;
+    }
+
+    /// Frees the player's seat before the game starts, afterwards it's kept for reconnecting and
+    /// their turns are passed while they're away
+    async fn handle_leave(&mut self, user_id: PlayerId) {
+        if !self.game.started {
+            if let Some((index, _, _)) = self.players.shift_remove_full(&user_id) {
+                self.game.remove_player(index).ok();
+            }
+            return;
         }
-        player.cards = player_cards
+        let Some(player) = self.players.get_mut(&user_id) else {
+            return;
+        };
+        player.connected = false;
+        player.left_at = Instant::now();
+        player.latency = None;
+        let content = format!("{} disconnected.", player.user.name);
+        self.broadcast_message(ChatMessage {
+            content: &content,
+            user_name: "SERVER",
+        })
+        .await;
+        self.broadcast_gamestate().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use parking_lot::Mutex;
+
+    use super::*;
+    use crate::AppState;
+
+    async fn snapshot(tx: &mpsc::Sender<Command>) -> RoomState {
+        let (sender, reply) = oneshot::channel();
+        tx.send(Command::Snapshot(sender)).await.unwrap();
+        reply.await.unwrap().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_disconnected_players_turn_is_passed() {
+        let state = SharedState::new(Mutex::new(AppState {
+            room_settings: RoomSettings {
+                reconnect: Duration::from_millis(300),
+                ..Default::default()
+            },
+            ..Default::default()
+        }));
+        let mut game = Game::new(Rules::default(), &DeckSpec::classic());
+        game.add_player().unwrap();
+        game.add_player().unwrap();
+        game.apply(Action::Start).unwrap();
+        let users: Vec<_> = ["alice", "bob"]
             .into_iter()
             .enumerate()
-            .filter_map(|(i, c)| (!card_indeces.contains(&i)).then(|| c))
+            .map(|(id, name)| User {
+                id,
+                name: name.into(),
+                ..User::new_empty()
+            })
             .collect();
-        game_state.next_turn(self);
-        self.cards_played += card_indeces.len();
-        self.broadcast_gamestate(game_state).await;
+        let room = RoomState {
+            name: "office".into(),
+            game,
+            seats: users.clone(),
+            reserved: None,
+        };
+        let (id, lobby) = RoomActor::spawn_restored(&state, Uuid::new_v4(), Uuid::new_v4(), room);
+        let tx = lobby.tx.clone();
+        state.lock().add_lobby(id, lobby);
+
+        // Only bob comes back, alice has the first turn
+        let (sender, reply) = oneshot::channel();
+        tx.send(Command::Join(Arc::new(users[1].clone()), sender))
+            .await
+            .unwrap();
+        let (_, mut rx, _) = reply.await.unwrap().unwrap();
+        tokio::spawn(async move { while rx.recv().await.is_some() {} });
+        assert_eq!(snapshot(&tx).await.game.state.turn_index, 0);
+
+        tokio::time::sleep(Duration::from_millis(600)).await;
+        let game = snapshot(&tx).await.game;
+        assert_eq!(game.state.turn_index, 1);
+        assert_eq!(game.players[0].cards.len(), 8);
     }
 }
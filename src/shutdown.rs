@@ -0,0 +1,95 @@
+use std::{sync::Arc, time::Duration};
+
+use tokio::{
+    signal,
+    sync::{mpsc, watch},
+    time::{sleep, timeout},
+};
+use tracing::{error, info, warn};
+
+use crate::{snapshot, AppState, Command, SharedState};
+
+/// How long connections get to close once the server stops
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Held by everything that has to hang up when the server stops
+pub type Closing = watch::Receiver<bool>;
+
+/// Resolves once the server starts closing connections, never if it doesn't
+pub async fn closing(closing: &mut Closing) {
+    if closing.wait_for(|&closing| closing).await.is_err() {
+        std::future::pending::<()>().await;
+    }
+}
+
+/// Resolves on Ctrl+C, or SIGTERM on unix
+async fn signal() {
+    let ctrl_c = async {
+        signal::ctrl_c().await.expect("Failed to listen for Ctrl+C");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        signal::unix::signal(signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        _ = ctrl_c => (),
+        _ = terminate => (),
+    }
+}
+
+/// Shuts down after a signal, for `axum::serve`'s graceful shutdown
+pub async fn on_signal(state: SharedState, grace: Duration) {
+    signal().await;
+    shutdown(state, grace).await;
+}
+
+fn room_senders(state: &AppState) -> Vec<mpsc::Sender<Command>> {
+    state.lobbies.values().map(|l| l.tx.clone()).collect()
+}
+
+/// Stops new lobbies from being made and warns every room, then after `grace` closes every
+/// connection and the rooms
+pub async fn shutdown(state: SharedState, grace: Duration) {
+    info!("Shutting down in {}s", grace.as_secs());
+    let rooms = {
+        let mut state = state.lock();
+        state.shutting_down = true;
+        state.matchmaking.queue.clear();
+        room_senders(&state)
+    };
+    for tx in rooms {
+        tx.send(Command::ServerShutdown(grace)).await.ok();
+    }
+    sleep(grace).await;
+
+    let closing = {
+        let state = state.lock();
+        state.closing.send_replace(true);
+        Arc::clone(&state.closing)
+    };
+    if timeout(CLOSE_TIMEOUT, closing.closed()).await.is_err() {
+        warn!(
+            "{} connections didn't close in time",
+            closing.receiver_count()
+        );
+    }
+    // Save before the rooms stop, so the games can be picked back up on the next start
+    let path = state.lock().snapshot_path.clone();
+    if let Some(path) = path {
+        match snapshot::save(&snapshot::take(&state).await, &path).await {
+            Ok(()) => info!("Saved snapshot to {}", path.display()),
+            Err(e) => error!("Failed to save snapshot: {e}"),
+        }
+    }
+    // Tables of running tournaments may have opened during the grace period
+    let rooms = room_senders(&state.lock());
+    for tx in rooms {
+        tx.send(Command::Shutdown).await.ok();
+    }
+    info!("Shut down");
+}
@@ -0,0 +1,313 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use futures_util::future::join_all;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs,
+    io::AsyncWriteExt,
+    sync::oneshot,
+    time::{interval, timeout},
+};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::{
+    room::{RoomActor, RoomState},
+    user::User,
+    Command, SharedState,
+};
+
+/// How long a room gets to hand over its game, busy or stuck rooms are left out of the snapshot
+const ROOM_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Everything needed to pick the games back up after a restart
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Session tokens and their users, so players stay logged in
+    pub users: Vec<(Uuid, User)>,
+    pub next_user_id: usize,
+    pub rooms: Vec<RoomSnapshot>,
+}
+
+/// A game in progress and its entry in the lobby list
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RoomSnapshot {
+    pub id: Uuid,
+    pub owner: Uuid,
+    pub private: bool,
+    pub password: Option<String>,
+    pub code: String,
+    pub room: RoomState,
+}
+
+/// Asks every room for its game at once, rooms without one in progress are left out
+pub async fn take(state: &SharedState) -> Snapshot {
+    let (mut snapshot, rooms) = {
+        let state = state.lock();
+        let snapshot = Snapshot {
+            users: state
+                .users
+                .iter()
+                .map(|(&token, user)| (token, User::clone(user)))
+                .collect(),
+            next_user_id: state.next_user_id,
+            rooms: Vec::new(),
+        };
+        let rooms: Vec<_> = state
+            .lobbies
+            .iter()
+            .map(|(&id, l)| (id, l.tx.clone()))
+            .collect();
+        (snapshot, rooms)
+    };
+    let replies = join_all(rooms.into_iter().map(|(id, tx)| async move {
+        let ask = async {
+            let (sender, reply) = oneshot::channel();
+            tx.send(Command::Snapshot(sender)).await.ok()?;
+            reply.await.ok()?
+        };
+        match timeout(ROOM_TIMEOUT, ask).await {
+            Ok(room) => room.map(|room| (id, room)),
+            Err(_) => {
+                warn!("Room {id} didn't answer in time, it's left out of the snapshot");
+                None
+            }
+        }
+    }))
+    .await;
+    let state = state.lock();
+    for (id, room) in replies.into_iter().flatten() {
+        // The room may have closed while we waited
+        let Some(lobby) = state.lobbies.get(&id) else {
+            continue;
+        };
+        snapshot.rooms.push(RoomSnapshot {
+            id,
+            owner: lobby.owner,
+            private: lobby.private,
+            password: lobby.password.clone(),
+            code: lobby.code.clone(),
+            room,
+        });
+    }
+    snapshot
+}
+
+/// Writes next to the file first so a crash can't leave half a snapshot behind. The snapshot
+/// holds session tokens and lobby passwords, so on unix only the owner can read it
+pub async fn save(snapshot: &Snapshot, path: &Path) -> Result<(), String> {
+    let json = serde_json::to_vec(snapshot).map_err(|e| e.to_string())?;
+    let tmp = path.with_extension("tmp");
+    write_private(&tmp, &json)
+        .await
+        .map_err(|e| format!("Can't write {}: {e}", tmp.display()))?;
+    fs::rename(&tmp, path)
+        .await
+        .map_err(|e| format!("Can't replace {}: {e}", path.display()))
+}
+
+async fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options.open(path).await?;
+    // The mode only applies to new files, a leftover from a crash keeps its own
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))
+            .await?;
+    }
+    file.write_all(contents).await?;
+    file.sync_all().await
+}
+
+/// Reads the snapshot at `path`, there's nothing to restore if it doesn't exist
+pub fn load(path: &Path) -> Result<Option<Snapshot>, String> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let json = std::fs::read(path).map_err(|e| format!("Can't read {}: {e}", path.display()))?;
+    serde_json::from_slice(&json)
+        .map(Some)
+        .map_err(|e| format!("Invalid snapshot {}: {e}", path.display()))
+}
+
+/// Logs the users back in and respawns the rooms, players rejoin them like after a disconnect
+pub fn restore(state: &SharedState, snapshot: Snapshot) {
+    let mut guard = state.lock();
+    for (token, user) in snapshot.users {
+        guard.taken_user_names.insert(user.name.clone());
+        guard.users.insert(token, Arc::new(user));
+    }
+    guard.next_user_id = guard.next_user_id.max(snapshot.next_user_id);
+    for saved in snapshot.rooms {
+        let (id, mut lobby) = RoomActor::spawn_restored(state, saved.id, saved.owner, saved.room);
+        lobby.private = saved.private;
+        lobby.password = saved.password;
+        lobby.code = saved.code;
+        if !guard.add_lobby(id, lobby) {
+            warn!("Couldn't restore room {id}, its name is taken");
+        }
+    }
+    info!(
+        "Restored {} users and {} rooms",
+        guard.users.len(),
+        guard.lobbies.len()
+    );
+}
+
+/// Saves a snapshot every `every`, runs for as long as the server does
+pub async fn run(state: SharedState, path: PathBuf, every: Duration) {
+    let mut timer = interval(every);
+    // The first tick is immediate, there's nothing new to save yet
+    timer.tick().await;
+    loop {
+        timer.tick().await;
+        let snapshot = take(&state).await;
+        if let Err(e) = save(&snapshot, &path).await {
+            error!("Failed to save snapshot: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use parking_lot::Mutex;
+
+    use super::*;
+    use crate::{
+        game::{Action, DeckSpec, Game, Rules},
+        AppState,
+    };
+
+    fn user(id: usize, name: &str) -> User {
+        User {
+            id,
+            name: name.into(),
+            ..User::new_empty()
+        }
+    }
+
+    /// A started game between the two users
+    fn snapshot(users: &[User]) -> Snapshot {
+        let mut game = Game::new(Rules::default(), &DeckSpec::classic());
+        for _ in users {
+            game.add_player().unwrap();
+        }
+        game.apply(Action::Start).unwrap();
+        Snapshot {
+            users: users.iter().map(|u| (Uuid::new_v4(), u.clone())).collect(),
+            next_user_id: users.len(),
+            rooms: vec![RoomSnapshot {
+                id: Uuid::new_v4(),
+                owner: Uuid::new_v4(),
+                private: false,
+                password: None,
+                code: "ABCDEF".into(),
+                room: RoomState {
+                    name: "office".into(),
+                    game,
+                    seats: users.to_vec(),
+                    reserved: None,
+                },
+            }],
+        }
+    }
+
+    async fn join(state: &SharedState, id: Uuid, user: User) -> Result<usize, String> {
+        let tx = state.lock().lobbies[&id].tx.clone();
+        let (sender, reply) = oneshot::channel();
+        tx.send(Command::Join(Arc::new(user), sender))
+            .await
+            .unwrap();
+        reply.await.unwrap().map(|(player, _, _)| player)
+    }
+
+    #[tokio::test]
+    async fn test_restore() {
+        let users = [user(0, "alice"), user(1, "bob")];
+        let snapshot = snapshot(&users);
+        let id = snapshot.rooms[0].id;
+        let token = snapshot.users[0].0;
+        let state = SharedState::new(Mutex::new(AppState::default()));
+        restore(&state, snapshot);
+        {
+            let state = state.lock();
+            assert_eq!(state.users[&token].name, "alice");
+            assert!(state.taken_user_names.contains("bob"));
+            assert_eq!(state.next_user_id, 2);
+            assert_eq!(state.lobby_codes["ABCDEF"], id);
+            let data = state.lobbies[&id].data.borrow();
+            assert_eq!(data.players, 2);
+            assert!(data.game_started);
+        }
+        assert_eq!(join(&state, id, users[1].clone()).await, Ok(1));
+        assert!(join(&state, id, user(2, "carol")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_take_after_restore() {
+        let users = [user(0, "alice"), user(1, "bob")];
+        let state = SharedState::new(Mutex::new(AppState::default()));
+        restore(&state, snapshot(&users));
+        let taken = take(&state).await;
+        assert_eq!(taken.users.len(), 2);
+        assert_eq!(taken.next_user_id, 2);
+        assert_eq!(taken.rooms.len(), 1);
+        let room = &taken.rooms[0];
+        assert_eq!(room.code, "ABCDEF");
+        assert_eq!(room.room.seats.len(), 2);
+        assert_eq!(room.room.game.players[0].cards.len(), 7);
+    }
+
+    #[tokio::test]
+    async fn test_save_and_load() {
+        let path = std::env::temp_dir().join(format!("{}.json", Uuid::new_v4()));
+        assert!(load(&path).unwrap().is_none());
+        save(&snapshot(&[user(0, "alice"), user(1, "bob")]), &path)
+            .await
+            .unwrap();
+        let loaded = load(&path).unwrap().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.users.len(), 2);
+        assert_eq!(loaded.rooms[0].room.name, "office");
+        assert!(loaded.rooms[0].room.game.started);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_save_is_owner_only() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = std::env::temp_dir().join(format!("{}.json", Uuid::new_v4()));
+        save(&Snapshot::default(), &path).await.unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[tokio::test]
+    async fn test_take_skips_stuck_rooms() {
+        let users = [user(0, "alice"), user(1, "bob")];
+        let state = SharedState::new(Mutex::new(AppState::default()));
+        restore(&state, snapshot(&users));
+        let mut other = snapshot(&[user(2, "carol"), user(3, "dave")]);
+        other.rooms[0].room.name = "kitchen".into();
+        other.rooms[0].code = "GHIJKL".into();
+        restore(&state, other);
+        assert_eq!(state.lock().lobbies.len(), 2);
+        // A room that never reads its commands
+        let (tx, _rx) = tokio::sync::mpsc::channel(1);
+        let stuck = state.lock().lobbies.keys().next().copied().unwrap();
+        state.lock().lobbies.get_mut(&stuck).unwrap().tx = tx;
+        let taken = take(&state).await;
+        assert_eq!(taken.rooms.len(), 1);
+        assert_ne!(taken.rooms[0].id, stuck);
+    }
+}
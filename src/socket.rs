@@ -0,0 +1,707 @@
+use std::{
+    borrow::Cow,
+    fmt::Display,
+    net::IpAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use axum::extract::ws::{CloseFrame, Message, WebSocket};
+use futures_util::{Sink, SinkExt, Stream, StreamExt};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+use tokio::{
+    select,
+    sync::{mpsc, oneshot},
+    time::{interval, timeout},
+};
+use tracing::info;
+
+use crate::{
+    game_messages::{Request, Response, Welcome, SUPPORTED_VERSIONS},
+    rate_limit::{RateLimit, TokenBucket},
+    shutdown::{self, Closing},
+    user::User,
+    Command, PlayerId, Ser, SharedState,
+};
+
+/// Close code sent when the client's protocol version isn't supported
+pub const CLOSE_INCOMPATIBLE_VERSION: u16 = 4000;
+/// Close code sent when the client doesn't open with a valid `Hello`
+pub const CLOSE_HANDSHAKE_FAILED: u16 = 4001;
+/// Close code sent when the client stops answering heartbeats
+pub const CLOSE_HEARTBEAT_TIMEOUT: u16 = 4002;
+/// Close code sent when the room shuts down
+pub const CLOSE_LOBBY_CLOSED: u16 = 4003;
+/// Close code sent when the lobby has a password and the client's `Hello` doesn't match it
+pub const CLOSE_WRONG_PASSWORD: u16 = 4004;
+/// Close code sent when the client keeps sending invalid requests
+pub const CLOSE_POLICY_VIOLATION: u16 = 1008;
+/// Close code sent when the server shuts down
+pub const CLOSE_GOING_AWAY: u16 = 1001;
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long to wait for the client to answer a close frame
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(1);
+/// How many invalid requests a client can send before it gets disconnected
+pub const MAX_VIOLATIONS: usize = 5;
+/// Maximum size of a single request in bytes, bigger frames end the connection before they're read
+pub const MAX_REQUEST_SIZE: usize = 4096;
+/// Maximum length of a chat message in characters
+pub const MAX_CHAT_MESSAGE_LENGTH: usize = 500;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Heartbeat {
+    /// How often the server pings the client
+    pub interval: Duration,
+    /// How long the client can go without answering a ping before it's disconnected
+    pub timeout: Duration,
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(10),
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// The password a client's `Hello` has to carry to join a lobby. Joining took a token from
+/// the IP's bucket in `password_limiter`, it's given back when the password is right
+pub struct Lock {
+    pub password: String,
+    pub ip: IpAddr,
+    pub app: SharedState,
+}
+
+impl Lock {
+    /// Compares hashes so the time taken doesn't depend on where the passwords differ or on
+    /// their lengths
+    fn opens(&self, given: &str) -> bool {
+        let expected = Sha256::digest(self.password.as_bytes());
+        Sha256::digest(given.as_bytes()).ct_eq(&expected).into()
+    }
+}
+
+pub async fn close<S>(socket: &mut S, code: u16, reason: impl Into<Cow<'static, str>>)
+where
+    S: Sink<Message> + Unpin,
+    S::Error: Display,
+{
+    socket
+        .send(Message::Close(Some(CloseFrame {
+            code,
+            reason: reason.into(),
+        })))
+        .await
+        .unwrap_or_else(|e| info!("Failed to close socket: {e}"));
+}
+
+/// Waits for the client's answer to a close frame. Dropping the socket before it arrives can
+/// reset the connection, and the client loses the close frame along with the reason
+async fn await_close_reply<S>(socket: &mut S)
+where
+    S: Stream<Item = Result<Message, axum::Error>> + Unpin,
+{
+    let reply = async {
+        while let Some(Ok(msg)) = socket.next().await {
+            if matches!(msg, Message::Close(_)) {
+                break;
+            }
+        }
+    };
+    timeout(CLOSE_TIMEOUT, reply).await.ok();
+}
+
+/// Waits for the client's `Hello` and returns the protocol version it speaks.
+/// Closes the socket and returns `None` if the handshake fails or the password doesn't open the
+/// `lock`
+async fn handshake(socket: &mut WebSocket, lock: Option<&Lock>) -> Option<u32> {
+    let Ok(Some(Ok(Message::Text(txt)))) = timeout(HANDSHAKE_TIMEOUT, socket.recv()).await else {
+        close(socket, CLOSE_HANDSHAKE_FAILED, "Expected Hello").await;
+        await_close_reply(socket).await;
+        return None;
+    };
+    let Ok(Request::Hello {
+        protocol_version,
+        client_name,
+        password: given,
+    }) = serde_json::from_str::<Request>(&txt)
+    else {
+        close(socket, CLOSE_HANDSHAKE_FAILED, "Expected Hello").await;
+        await_close_reply(socket).await;
+        return None;
+    };
+    info!("Hello from {client_name}, protocol version {protocol_version}");
+    if !SUPPORTED_VERSIONS.contains(&protocol_version) {
+        close(
+            socket,
+            CLOSE_INCOMPATIBLE_VERSION,
+            format!("Unsupported protocol version {protocol_version}, supported: {SUPPORTED_VERSIONS:?}"),
+        )
+        .await;
+        await_close_reply(socket).await;
+        return None;
+    }
+    if let Some(lock) = lock {
+        if !lock.opens(&given.unwrap_or_default()) {
+            close(socket, CLOSE_WRONG_PASSWORD, "Wrong password").await;
+            await_close_reply(socket).await;
+            return None;
+        }
+        lock.app.lock().password_limiter.put_back(&lock.ip);
+    }
+    Some(protocol_version)
+}
+
+/// Runs a player's connection to a room. `lock` is set if the lobby has a password
+pub async fn handle_socket(
+    mut socket: WebSocket,
+    tx: mpsc::Sender<Command>,
+    lock: Option<Lock>,
+    user: Arc<User>,
+    limit: RateLimit,
+    heartbeat: Heartbeat,
+    mut closing: Closing,
+) {
+    let Some(protocol_version) = handshake(&mut socket, lock.as_ref()).await else {
+        return;
+    };
+    let (oneshot_tx, oneshot_rx) = oneshot::channel();
+
+    if tx.send(Command::Join(user, oneshot_tx)).await.is_err() {
+        close(&mut socket, 1011, "Lobby is closed").await;
+        await_close_reply(&mut socket).await;
+        return;
+    }
+    let res = oneshot_rx
+        .await
+        .unwrap_or_else(|_| Err("Lobby is closed".into()));
+
+    let (self_id, mut room_rx, rules) = match res {
+        Ok(t) => t,
+        Err(err) => {
+            close(&mut socket, 1011, err).await;
+            await_close_reply(&mut socket).await;
+            return;
+        }
+    };
+    let welcome = Response::Welcome(Welcome {
+        protocol_version,
+        supported_versions: SUPPORTED_VERSIONS,
+        rules: &rules,
+    });
+    if socket.send(Message::Text(welcome.ser())).await.is_err() {
+        tx.send(Command::Leave(self_id)).await.ok();
+        return;
+    }
+
+    let (mut write, mut read) = socket.split();
+    let mut violations = 0;
+    let mut bucket = TokenBucket::new(limit);
+    let mut throttled = false;
+    let mut ping_timer = interval(heartbeat.interval);
+    let mut ping_sent_at = None;
+    let mut last_pong = Instant::now();
+    loop {
+        select! {
+            _ = ping_timer.tick() => {
+                if last_pong.elapsed() > heartbeat.timeout {
+                    info!("Player {self_id} missed its heartbeat");
+                    close(&mut write, CLOSE_HEARTBEAT_TIMEOUT, "Heartbeat timed out").await;
+                    await_close_reply(&mut read).await;
+                    break;
+                }
+                ping_sent_at = Some(Instant::now());
+                if write.send(Message::Ping(Vec::new())).await.is_err() {
+                    break;
+                }
+            }
+            msg = read.next() => {
+                let Some(Ok(msg)) = msg else { break };
+                if !matches!(msg, Message::Ping(_) | Message::Pong(_)) {
+                    info!("Request: {msg:?}");
+                }
+                if matches!(msg, Message::Text(_) | Message::Binary(_)) && !bucket.try_take() {
+                    // Only tell the client once per flood, the dropped requests don't need answers
+                    if !throttled {
+                        throttled = true;
+                        let err = Response::Error("Too many requests, slow down".into());
+                        if write.send(Message::Text(err.ser())).await.is_err() {
+                            break;
+                        }
+                    }
+                    continue;
+                }
+                throttled = false;
+                let cmd = match msg {
+                    Message::Text(txt) => parse_request(self_id, &txt),
+                    Message::Binary(_) => Err("Binary messages aren't supported".into()),
+                    Message::Pong(_) => {
+                        last_pong = Instant::now();
+                        match ping_sent_at.take() {
+                            Some(sent_at) => Ok(Command::Latency(self_id, sent_at.elapsed())),
+                            None => continue,
+                        }
+                    }
+                    Message::Close(_) => break,
+                    Message::Ping(_) => continue,
+                };
+                match cmd {
+                    Ok(cmd) => {
+                        if tx.send(cmd).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(err) => {
+                        violations += 1;
+                        if violations >= MAX_VIOLATIONS {
+                            close(&mut write, CLOSE_POLICY_VIOLATION, "Too many invalid requests").await;
+                            await_close_reply(&mut read).await;
+                            break;
+                        }
+                        if write.send(Message::Text(Response::Error(err).ser())).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+            _ = shutdown::closing(&mut closing) => {
+                close(&mut write, CLOSE_GOING_AWAY, "Server is shutting down").await;
+                await_close_reply(&mut read).await;
+                break;
+            }
+            msg = room_rx.recv() => {
+                let Some(msg) = msg else {
+                    close(&mut write, CLOSE_LOBBY_CLOSED, "Lobby is closed").await;
+                    await_close_reply(&mut read).await;
+                    break;
+                };
+                if write.send(Message::Text(msg)).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+    tx.send(Command::Leave(self_id)).await.ok();
+}
+
+/// Parses and validates a single request, returning the error sent back to the client on failure
+fn parse_request(self_id: PlayerId, txt: &str) -> Result<Command, String> {
+    let request =
+        serde_json::from_str::<Request>(txt).map_err(|e| format!("Malformed request: {e}"))?;
+    Ok(match request {
+        Request::SendMessage { content } => {
+            if content.chars().count() > MAX_CHAT_MESSAGE_LENGTH {
+                return Err(format!(
+                    "Chat messages can be at most {MAX_CHAT_MESSAGE_LENGTH} characters"
+                ));
+            }
+            Command::SendMessage(self_id, content)
+        }
+        Request::PlaySpecialCard(i, c) => Command::PlayCard(self_id, i, c),
+        Request::TakeCard => Command::TakeCard(self_id),
+        Request::PlayCards(cards) => Command::PlayCards(self_id, cards),
+        Request::PlayTargetedCard {
+            card,
+            color,
+            target,
+        } => Command::PlayTargetedCard(self_id, card, color, target),
+        Request::ChooseSwapTarget(target) => Command::ChooseSwapTarget(self_id, target),
+        Request::JumpIn { card, cards_played } => Command::JumpIn(self_id, card, cards_played),
+        Request::GiveToPartner(card) => Command::GiveToPartner(self_id, card),
+        Request::Hello { .. } => return Err("Already greeted".into()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use axum::Router;
+    use futures_util::{SinkExt, StreamExt};
+    use parking_lot::Mutex;
+    use rand::{distributions::Alphanumeric, Rng};
+    use serde_json::Value;
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_tungstenite::{
+        connect_async,
+        tungstenite::{client::IntoClientRequest, handshake::client::Request, Error, Message},
+        MaybeTlsStream, WebSocketStream,
+    };
+
+    use super::*;
+    use crate::{
+        game::{DeckSpec, Rules},
+        rate_limit::RateLimits,
+        room::{RoomActor, RoomSettings},
+        user::UserCreate,
+        AppState, SharedState, SESSION_TOKEN,
+    };
+
+    type Client = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+    /// Starts a server with a single lobby and opens a socket to it without saying hello
+    async fn connect() -> Client {
+        connect_with_limits(RateLimits::default()).await
+    }
+
+    async fn connect_with_limits(rate_limits: RateLimits) -> Client {
+        connect_with_state(AppState::new(rate_limits)).await
+    }
+
+    async fn connect_with_state(state: AppState) -> Client {
+        start(state).await.0
+    }
+
+    /// Starts a server with a single lobby and opens a socket to it, returning the state too
+    async fn start(state: AppState) -> (Client, SharedState) {
+        let (state, request) = serve(state).await;
+        (connect_async(request).await.unwrap().0, state)
+    }
+
+    /// Starts a server with a single lobby with the password `secret`, returning the request
+    /// that joins it
+    async fn serve(state: AppState) -> (SharedState, Request) {
+        let state = SharedState::new(Mutex::new(state));
+        let token = state
+            .lock()
+            .new_user(UserCreate {
+                name: "fuzzer".into(),
+                avatar: User::new_empty().avatar,
+            })
+            .unwrap();
+        let (id, mut lobby) = RoomActor::spawn_new(
+            &state,
+            token,
+            "fuzz".into(),
+            Rules::default(),
+            DeckSpec::classic(),
+        );
+        lobby.password = Some("secret".into());
+        state.lock().add_lobby(id, lobby);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = crate::app(SharedState::clone(&state), Router::new())
+            .into_make_service_with_connect_info::<SocketAddr>();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let mut request = format!("ws://{addr}/api/lobbies/join/{id}")
+            .into_client_request()
+            .unwrap();
+        request.headers_mut().insert(
+            "Cookie",
+            format!("{SESSION_TOKEN}={token}").parse().unwrap(),
+        );
+        (state, request)
+    }
+
+    /// A request that joins the same lobby as `request` as a new user
+    fn join_as(state: &SharedState, request: &Request, name: &str) -> Request {
+        let token = state
+            .lock()
+            .new_user(UserCreate {
+                name: name.into(),
+                avatar: User::new_empty().avatar,
+            })
+            .unwrap();
+        let mut request = request.uri().into_client_request().unwrap();
+        request.headers_mut().insert(
+            "Cookie",
+            format!("{SESSION_TOKEN}={token}").parse().unwrap(),
+        );
+        request
+    }
+
+    async fn hello(client: &mut Client, protocol_version: u32) {
+        hello_with_password(client, protocol_version, r#""secret""#).await;
+    }
+
+    /// Says hello with `password` as raw JSON
+    async fn hello_with_password(client: &mut Client, protocol_version: u32, password: &str) {
+        let hello = format!(
+            r#"{{"tag":"Hello","fields":{{"protocol_version":{protocol_version},"client_name":"test","password":{password}}}}}"#
+        );
+        client.send(Message::Text(hello)).await.unwrap();
+    }
+
+    /// Says hello and waits for the welcome
+    async fn connect_greeted() -> Client {
+        greet(connect().await).await
+    }
+
+    async fn greet(mut client: Client) -> Client {
+        hello(&mut client, SUPPORTED_VERSIONS[0]).await;
+        recv_tag(&mut client, "Welcome").await;
+        client
+    }
+
+    /// Skips messages until one with the given tag arrives
+    async fn recv_tag(client: &mut Client, tag: &str) -> Value {
+        loop {
+            match client.next().await {
+                Some(Ok(Message::Text(txt))) => {
+                    let value: Value = serde_json::from_str(&txt).unwrap();
+                    if value["tag"] == tag {
+                        return value;
+                    }
+                }
+                Some(Ok(_)) => (),
+                other => panic!("expected {tag}, got {other:?}"),
+            }
+        }
+    }
+
+    /// Skips messages until the server closes the socket, returning the close code
+    async fn recv_close(client: &mut Client) -> u16 {
+        loop {
+            match client.next().await {
+                Some(Ok(Message::Close(Some(frame)))) => return frame.code.into(),
+                Some(Ok(_)) => (),
+                other => panic!("expected close, got {other:?}"),
+            }
+        }
+    }
+
+    fn garbage_frame() -> Message {
+        let mut rng = rand::thread_rng();
+        let len = rng.gen_range(0..64);
+        match rng.gen_range(0..4) {
+            0 => Message::Binary((0..len).map(|_| rng.gen()).collect()),
+            1 => Message::Text(
+                (&mut rng)
+                    .sample_iter(Alphanumeric)
+                    .take(len)
+                    .map(char::from)
+                    .collect(),
+            ),
+            2 => Message::Text(r#"{"tag":"PlayCards","fields":"nope"}"#.into()),
+            _ => Message::Text(r#"{"tag":"DoesNotExist"}"#.into()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handshake_incompatible_version() {
+        let mut client = connect().await;
+        hello(&mut client, 0).await;
+        assert_eq!(recv_close(&mut client).await, CLOSE_INCOMPATIBLE_VERSION);
+    }
+
+    #[tokio::test]
+    async fn test_handshake_garbage() {
+        let mut client = connect().await;
+        client.send(garbage_frame()).await.unwrap();
+        assert_eq!(recv_close(&mut client).await, CLOSE_HANDSHAKE_FAILED);
+    }
+
+    #[tokio::test]
+    async fn test_garbage_frames_get_errors() {
+        let mut client = connect_greeted().await;
+        for _ in 1..MAX_VIOLATIONS {
+            client.send(garbage_frame()).await.unwrap();
+            recv_tag(&mut client, "Error").await;
+        }
+        // Still connected after the errors
+        let chat = r#"{"tag":"SendMessage","fields":{"content":"still here"}}"#;
+        client.send(Message::Text(chat.into())).await.unwrap();
+        let msg = recv_tag(&mut client, "ChatMessage").await;
+        assert_eq!(msg["fields"]["content"], "still here");
+    }
+
+    #[tokio::test]
+    async fn test_too_many_violations_disconnects() {
+        let mut client = connect_greeted().await;
+        for _ in 0..MAX_VIOLATIONS {
+            client.send(garbage_frame()).await.unwrap();
+        }
+        assert_eq!(recv_close(&mut client).await, CLOSE_POLICY_VIOLATION);
+    }
+
+    #[tokio::test]
+    async fn test_chat_message_too_long() {
+        let mut client = connect_greeted().await;
+        let content = "a".repeat(MAX_CHAT_MESSAGE_LENGTH + 1);
+        let chat = format!(r#"{{"tag":"SendMessage","fields":{{"content":"{content}"}}}}"#);
+        client.send(Message::Text(chat)).await.unwrap();
+        recv_tag(&mut client, "Error").await;
+    }
+
+    #[tokio::test]
+    async fn test_flood_is_throttled() {
+        let mut rate_limits = RateLimits::default();
+        rate_limits.socket.per_second = 0.0;
+        let mut client = greet(connect_with_limits(rate_limits).await).await;
+        let chat = r#"{"tag":"SendMessage","fields":{"content":"spam"}}"#;
+        for _ in 0..=rate_limits.socket.burst {
+            client.send(Message::Text(chat.into())).await.unwrap();
+        }
+        let err = recv_tag(&mut client, "Error").await;
+        assert_eq!(err["fields"], "Too many requests, slow down");
+    }
+
+    #[tokio::test]
+    async fn test_missed_heartbeat_disconnects() {
+        let state = AppState {
+            heartbeat: Heartbeat {
+                interval: Duration::from_millis(50),
+                timeout: Duration::from_millis(300),
+            },
+            ..Default::default()
+        };
+        let (state, request) = serve(state).await;
+        let second = join_as(&state, &request, "second");
+        let mut client = greet(connect_async(request).await.unwrap().0).await;
+        let mut other = greet(connect_async(second).await.unwrap().0).await;
+        let start = r#"{"tag":"SendMessage","fields":{"content":"/start"}}"#;
+        client.send(Message::Text(start.into())).await.unwrap();
+        // The other player keeps reading, so it answers its pings, until the game shows the
+        // seat as disconnected
+        let disconnected = tokio::spawn(async move {
+            loop {
+                let msg = recv_tag(&mut other, "GameState").await;
+                if msg["fields"]["users"][0]["connected"] == false {
+                    break;
+                }
+            }
+        });
+        // Not reading means the client never answers the pings. The server waits a moment for
+        // the close reply, the client has to read before that or its pongs hit a closed socket
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert_eq!(recv_close(&mut client).await, CLOSE_HEARTBEAT_TIMEOUT);
+        timeout(Duration::from_secs(2), disconnected)
+            .await
+            .expect("seat wasn't marked as disconnected")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_lobby_data_follows_joins() {
+        let (client, state) = start(AppState::default()).await;
+        let mut client = greet(client).await;
+        recv_tag(&mut client, "GameState").await;
+        assert_eq!(state.lock().lobby_data()[0].players, 1);
+        client.close(None).await.unwrap();
+        while client.next().await.is_some() {}
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(state.lock().lobby_data()[0].players, 0);
+    }
+
+    #[tokio::test]
+    async fn test_wrong_password_is_refused() {
+        for password in [r#""guess""#, "null"] {
+            let (mut client, state) = start(AppState::default()).await;
+            hello_with_password(&mut client, SUPPORTED_VERSIONS[0], password).await;
+            assert_eq!(recv_close(&mut client).await, CLOSE_WRONG_PASSWORD);
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            assert_eq!(state.lock().lobby_data()[0].players, 0);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_wrong_passwords_are_limited() {
+        let rate_limits = RateLimits {
+            password: RateLimit {
+                burst: 2,
+                per_second: 0.001,
+            },
+            ..Default::default()
+        };
+        let (_state, request) = serve(AppState::new(rate_limits)).await;
+        let again = || {
+            let mut copy = request.uri().into_client_request().unwrap();
+            copy.headers_mut()
+                .insert("Cookie", request.headers()["Cookie"].clone());
+            copy
+        };
+        // Right passwords don't count
+        for _ in 0..3 {
+            let mut client = greet(connect_async(again()).await.unwrap().0).await;
+            client.close(None).await.unwrap();
+        }
+        for _ in 0..2 {
+            let mut client = connect_async(again()).await.unwrap().0;
+            hello_with_password(&mut client, SUPPORTED_VERSIONS[0], r#""guess""#).await;
+            assert_eq!(recv_close(&mut client).await, CLOSE_WRONG_PASSWORD);
+        }
+        match connect_async(again()).await {
+            Err(Error::Http(response)) => assert_eq!(response.status(), 429),
+            other => panic!("expected a refusal, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_closes_sockets() {
+        let (client, state) = start(AppState::default()).await;
+        let mut client = greet(client).await;
+        let tx = state.lock().lobbies.values().next().unwrap().tx.clone();
+        tx.send(Command::Shutdown).await.unwrap();
+        assert_eq!(recv_close(&mut client).await, CLOSE_LOBBY_CLOSED);
+        assert!(state.lock().lobbies.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_server_shutdown() {
+        let (client, state) = start(AppState::default()).await;
+        let mut client = greet(client).await;
+        let grace = Duration::from_secs(1);
+        let shutdown = tokio::spawn(shutdown::shutdown(SharedState::clone(&state), grace));
+        let notice = recv_tag(&mut client, "ServerShutdown").await;
+        assert_eq!(notice["fields"]["in_seconds"], 1);
+        assert!(state.lock().shutting_down);
+        assert_eq!(recv_close(&mut client).await, CLOSE_GOING_AWAY);
+        shutdown.await.unwrap();
+        assert_eq!(state.lock().closing.receiver_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_request_disconnects() {
+        let (client, state) = start(AppState::default()).await;
+        let mut client = greet(client).await;
+        let content = "a".repeat(MAX_REQUEST_SIZE);
+        let chat = format!(r#"{{"tag":"SendMessage","fields":{{"content":"{content}"}}}}"#);
+        client.send(Message::Text(chat)).await.ok();
+        // Dropped without being read, so there's no error to answer with
+        while let Some(Ok(msg)) = client.next().await {
+            if let Message::Text(txt) = msg {
+                assert!(!txt.contains(r#""tag":"Error""#), "got {txt}");
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(state.lock().lobby_data()[0].players, 0);
+    }
+
+    #[tokio::test]
+    async fn test_idle_room_closes() {
+        let state = AppState {
+            room_settings: RoomSettings {
+                idle: Duration::from_millis(100),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let (client, state) = start(state).await;
+        let mut client = greet(client).await;
+        assert_eq!(recv_close(&mut client).await, CLOSE_LOBBY_CLOSED);
+        assert!(state.lock().lobbies.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_empty_room_closes() {
+        let state = AppState {
+            room_settings: RoomSettings {
+                empty: Duration::from_millis(100),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let (client, state) = start(state).await;
+        let mut client = greet(client).await;
+        // Still open while someone is in it
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(state.lock().lobbies.len(), 1);
+        client.close(None).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        assert!(state.lock().lobbies.is_empty());
+    }
+}
@@ -0,0 +1,753 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket},
+        Path, State, WebSocketUpgrade,
+    },
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::{broadcast, oneshot},
+    time::sleep,
+};
+use tracing::info;
+use ts_rs::TS;
+use uuid::Uuid;
+
+use crate::{
+    game::{DeckSpec, GameMode, Rules},
+    lobby::{invalid, validate_name, FieldError, LobbyLimits, RuleChoices},
+    room::RoomActor,
+    shutdown::{self, Closing},
+    socket::{close, CLOSE_GOING_AWAY},
+    token_extractor::SessionToken,
+    user::User,
+    Ser, SharedState,
+};
+
+/// How long a finished tournament's bracket can still be looked at
+const KEEP_FINISHED: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Deserialize, Debug, TS)]
+#[ts(export)]
+struct CreateTournamentData {
+    name: String,
+    /// How many players sit at each table
+    table_size: usize,
+    /// Defaults to the classic deck
+    deck: Option<String>,
+    #[serde(default)]
+    seven_o: bool,
+    #[serde(default)]
+    jump_in: bool,
+    /// Tables can't be played in teams
+    #[serde(default)]
+    mode: GameMode,
+}
+
+#[derive(Debug)]
+pub struct Table {
+    /// The table's room, byes don't get one
+    pub lobby: Option<Uuid>,
+    pub players: Vec<Arc<User>>,
+    pub winner: Option<Arc<User>>,
+    /// The table's room closed before anyone won, nobody advances from it
+    pub abandoned: bool,
+}
+
+impl Table {
+    fn done(&self) -> bool {
+        self.winner.is_some() || self.abandoned
+    }
+}
+
+/// Who plays who, the winners of each round's tables play each other in the next one
+#[derive(Debug)]
+pub struct Bracket {
+    pub name: String,
+    pub table_size: usize,
+    pub entrants: Vec<Arc<User>>,
+    pub rounds: Vec<Vec<Table>>,
+    pub winner: Option<Arc<User>>,
+}
+
+impl Bracket {
+    pub fn new(name: String, table_size: usize) -> Self {
+        Self {
+            name,
+            table_size,
+            entrants: Vec::new(),
+            rounds: Vec::new(),
+            winner: None,
+        }
+    }
+
+    pub fn started(&self) -> bool {
+        !self.rounds.is_empty()
+    }
+
+    /// Every table of the last round is done and nobody is left to play
+    pub fn finished(&self) -> bool {
+        self.rounds
+            .last()
+            .is_some_and(|r| r.iter().all(Table::done))
+    }
+
+    pub fn join(&mut self, user: Arc<User>) -> Result<(), &'static str> {
+        if self.started() {
+            return Err("Tournament has already started.");
+        }
+        if self.entrants.iter().any(|u| u.id == user.id) {
+            return Err("Already joined.");
+        }
+        self.entrants.push(user);
+        Ok(())
+    }
+
+    /// Shuffles the entrants into the first round's tables
+    pub fn seed(&mut self) -> Result<(), &'static str> {
+        if self.started() {
+            return Err("Tournament has already started.");
+        }
+        if self.entrants.len() < 2 {
+            return Err("Tournament needs at least 2 players.");
+        }
+        let mut players = self.entrants.clone();
+        players.shuffle(&mut rand::thread_rng());
+        self.push_round(players);
+        Ok(())
+    }
+
+    /// Deals the players out to as few tables as possible, table sizes differ by at most one.
+    /// A player alone at a table gets a bye
+    fn push_round(&mut self, players: Vec<Arc<User>>) {
+        let count = players.len().div_ceil(self.table_size);
+        let mut tables: Vec<Table> = (0..count)
+            .map(|_| Table {
+                lobby: None,
+                players: Vec::new(),
+                winner: None,
+                abandoned: false,
+            })
+            .collect();
+        for (i, player) in players.into_iter().enumerate() {
+            tables[i % count].players.push(player);
+        }
+        for table in &mut tables {
+            if let [player] = table.players.as_slice() {
+                table.winner = Some(Arc::clone(player));
+            }
+        }
+        self.rounds.push(tables);
+    }
+
+    /// Records a table's winner. Returns true if that finished the round and a new one was
+    /// seeded from the winners
+    pub fn record(&mut self, round: usize, table: usize, winner: usize) -> bool {
+        if round + 1 != self.rounds.len() {
+            return false;
+        }
+        let Some(table) = self.rounds[round].get_mut(table) else {
+            return false;
+        };
+        let Some(user) = table.players.iter().find(|u| u.id == winner) else {
+            return false;
+        };
+        if table.abandoned {
+            return false;
+        }
+        table.winner.get_or_insert_with(|| Arc::clone(user));
+        self.next_round()
+    }
+
+    /// Gives up on a table whose room closed without a winner. Returns true like `record`
+    pub fn abandon(&mut self, round: usize, table: usize) -> bool {
+        if round + 1 != self.rounds.len() {
+            return false;
+        }
+        let Some(table) = self.rounds[round].get_mut(table) else {
+            return false;
+        };
+        if table.winner.is_some() {
+            return false;
+        }
+        table.abandoned = true;
+        self.next_round()
+    }
+
+    /// Seeds the next round from the winners once every table of the last one is done.
+    /// Returns true if it did
+    fn next_round(&mut self) -> bool {
+        let Some(round) = self.rounds.last().filter(|r| r.iter().all(Table::done)) else {
+            return false;
+        };
+        let winners: Vec<Arc<User>> = round.iter().filter_map(|t| t.winner.clone()).collect();
+        match winners.as_slice() {
+            // Every table was abandoned, the tournament ends without a winner
+            [] => false,
+            [winner] => {
+                self.winner = Some(Arc::clone(winner));
+                false
+            }
+            _ => {
+                self.push_round(winners);
+                true
+            }
+        }
+    }
+
+    pub fn data(&self, id: Uuid) -> TournamentData {
+        let name = |u: &Arc<User>| u.name.clone();
+        TournamentData {
+            id,
+            name: self.name.clone(),
+            table_size: self.table_size,
+            started: self.started(),
+            finished: self.finished(),
+            players: self.entrants.iter().map(name).collect(),
+            rounds: self
+                .rounds
+                .iter()
+                .map(|round| {
+                    round
+                        .iter()
+                        .map(|t| TableData {
+                            lobby: t.lobby,
+                            players: t.players.iter().map(name).collect(),
+                            winner: t.winner.as_ref().map(name),
+                            abandoned: t.abandoned,
+                        })
+                        .collect()
+                })
+                .collect(),
+            winner: self.winner.as_ref().map(name),
+        }
+    }
+}
+
+pub struct Tournament {
+    pub owner: Uuid,
+    pub bracket: Bracket,
+    pub rules: Rules,
+    pub deck: DeckSpec,
+    /// Serialized `TournamentData`, sent whenever the bracket changes
+    pub updates: broadcast::Sender<String>,
+}
+
+#[derive(Serialize, Debug, TS)]
+#[ts(export)]
+pub struct TableData {
+    /// Join the table with this lobby id
+    pub lobby: Option<Uuid>,
+    pub players: Vec<String>,
+    pub winner: Option<String>,
+    pub abandoned: bool,
+}
+
+#[derive(Serialize, Debug, TS)]
+#[ts(export)]
+pub struct TournamentData {
+    pub id: Uuid,
+    pub name: String,
+    pub table_size: usize,
+    pub started: bool,
+    /// Can be true without a winner if every table of a round was abandoned
+    pub finished: bool,
+    pub players: Vec<String>,
+    pub rounds: Vec<Vec<TableData>>,
+    pub winner: Option<String>,
+}
+
+/// Sends the tournament's bracket to everyone watching it
+fn publish(tournament: &Tournament, id: Uuid) {
+    tournament
+        .updates
+        .send(tournament.bracket.data(id).ser())
+        .ok();
+}
+
+/// Spawns a room for every table in the latest round that is still waiting for one
+fn spawn_tables(state: &SharedState, id: Uuid) {
+    let mut guard = state.lock();
+    // Tables close when the server stops, that doesn't open the next round
+    if guard.shutting_down {
+        return;
+    }
+    let Some(tournament) = guard.tournaments.get(&id) else {
+        return;
+    };
+    let round = tournament.bracket.rounds.len() - 1;
+    let open: Vec<(usize, Rules, String, Vec<usize>)> = tournament.bracket.rounds[round]
+        .iter()
+        .enumerate()
+        .filter(|(_, t)| t.lobby.is_none() && !t.done())
+        .map(|(index, table)| {
+            let rules = Rules {
+                max_players: table.players.len(),
+                ..tournament.rules.clone()
+            };
+            let name = format!(
+                "{} - Round {} Table {}",
+                tournament.bracket.name,
+                round + 1,
+                index + 1
+            );
+            (
+                index,
+                rules,
+                name,
+                table.players.iter().map(|u| u.id).collect(),
+            )
+        })
+        .collect();
+    let (owner, deck) = (tournament.owner, tournament.deck.clone());
+    for (index, rules, name, users) in open {
+        let (on_finish, result) = oneshot::channel();
+        let name = guard.free_lobby_name(name);
+        let (lobby_id, lobby) = RoomActor::spawn_table(
+            state,
+            owner,
+            name,
+            rules,
+            deck.clone(),
+            users,
+            Some(on_finish),
+        );
+        guard.add_lobby(lobby_id, lobby);
+        if let Some(tournament) = guard.tournaments.get_mut(&id) {
+            tournament.bracket.rounds[round][index].lobby = Some(lobby_id);
+        }
+
+        let state = SharedState::clone(state);
+        // The room drops `on_finish` if it closes without a winner, after a no-show or
+        // timing out
+        tokio::spawn(async move {
+            advance(&state, id, round, index, result.await.ok());
+        });
+    }
+    if let Some(tournament) = guard.tournaments.get(&id) {
+        publish(tournament, id);
+    }
+}
+
+/// Records how a table ended, a table without a winner was abandoned
+fn advance(state: &SharedState, id: Uuid, round: usize, table: usize, winner: Option<usize>) {
+    let (next_round, finished) = {
+        let mut state = state.lock();
+        let Some(tournament) = state.tournaments.get_mut(&id) else {
+            return;
+        };
+        let bracket = &mut tournament.bracket;
+        let next_round = match winner {
+            Some(winner) => bracket.record(round, table, winner),
+            None => {
+                info!(
+                    "Tournament {} round {} table {} was abandoned",
+                    bracket.name,
+                    round + 1,
+                    table + 1
+                );
+                bracket.abandon(round, table)
+            }
+        };
+        let finished = bracket.finished();
+        if finished {
+            match &bracket.winner {
+                Some(winner) => info!("Tournament {} won by {}", bracket.name, winner.name),
+                None => info!("Tournament {} ended without a winner", bracket.name),
+            }
+        }
+        publish(tournament, id);
+        (next_round, finished)
+    };
+    if next_round {
+        spawn_tables(state, id);
+    }
+    if finished {
+        tokio::spawn(remove_finished(SharedState::clone(state), id));
+    }
+}
+
+/// Forgets the tournament once people had time to see the final bracket, its feeds close
+async fn remove_finished(state: SharedState, id: Uuid) {
+    sleep(KEEP_FINISHED).await;
+    state.lock().tournaments.remove(&id);
+}
+
+impl CreateTournamentData {
+    /// Checks the name and the tables' rules
+    fn validate(
+        &self,
+        limits: &LobbyLimits,
+        decks: &HashMap<String, DeckSpec>,
+    ) -> Result<(Rules, DeckSpec), Vec<FieldError>> {
+        // The name goes into the tables' lobby names, so it follows the same rules
+        let mut errors: Vec<_> = validate_name(&self.name, "Tournament", limits)
+            .into_iter()
+            .collect();
+        if self.mode == GameMode::Teams {
+            errors.push(FieldError::new(
+                "mode",
+                "Tournament tables can't be played in teams.",
+            ));
+        }
+        let choices = RuleChoices {
+            players: self.table_size,
+            deck: self.deck.clone(),
+            seven_o: self.seven_o,
+            jump_in: self.jump_in,
+            mode: self.mode,
+            partner_exchange: false,
+        };
+        match choices.validate("table_size", limits, decks, &mut errors) {
+            Some(valid) if errors.is_empty() => Ok(valid),
+            _ => Err(errors),
+        }
+    }
+}
+
+async fn tournaments_create(
+    SessionToken(token): SessionToken,
+    State(state): State<SharedState>,
+    Json(input): Json<CreateTournamentData>,
+) -> Response {
+    info!("New tournament {input:?}");
+    if state.lock().shutting_down {
+        return (StatusCode::SERVICE_UNAVAILABLE, "Server is shutting down.").into_response();
+    }
+    if !state.lock().users.contains_key(&token) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    let result = {
+        let state = state.lock();
+        input.validate(&state.lobby_limits, &state.decks)
+    };
+    let (rules, deck) = match result {
+        Ok(valid) => valid,
+        Err(errors) => return invalid(errors),
+    };
+    let id = Uuid::new_v4();
+    state.lock().tournaments.insert(
+        id,
+        Tournament {
+            owner: token,
+            bracket: Bracket::new(input.name, input.table_size),
+            rules,
+            deck,
+            updates: broadcast::channel(16).0,
+        },
+    );
+    (StatusCode::CREATED, Json(id)).into_response()
+}
+
+async fn tournament_view(
+    State(state): State<SharedState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<TournamentData>, (StatusCode, &'static str)> {
+    let state = state.lock();
+    let tournament = state
+        .tournaments
+        .get(&id)
+        .ok_or((StatusCode::NOT_FOUND, "Tournament doesn't exist"))?;
+    Ok(Json(tournament.bracket.data(id)))
+}
+
+async fn tournament_join(
+    SessionToken(token): SessionToken,
+    State(state): State<SharedState>,
+    Path(id): Path<Uuid>,
+) -> Response {
+    let mut state = state.lock();
+    let Some(user) = state.users.get(&token).map(Arc::clone) else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+    let Some(tournament) = state.tournaments.get_mut(&id) else {
+        return (StatusCode::NOT_FOUND, "Tournament doesn't exist").into_response();
+    };
+    if let Err(e) = tournament.bracket.join(user) {
+        return (StatusCode::BAD_REQUEST, e).into_response();
+    }
+    publish(tournament, id);
+    StatusCode::OK.into_response()
+}
+
+/// Seeds the first round and opens its tables, only the tournament's creator can start it
+async fn tournament_start(
+    SessionToken(token): SessionToken,
+    State(state): State<SharedState>,
+    Path(id): Path<Uuid>,
+) -> Response {
+    {
+        let mut state = state.lock();
+        if state.shutting_down {
+            return (StatusCode::SERVICE_UNAVAILABLE, "Server is shutting down.").into_response();
+        }
+        let Some(tournament) = state.tournaments.get_mut(&id) else {
+            return (StatusCode::NOT_FOUND, "Tournament doesn't exist").into_response();
+        };
+        if tournament.owner != token {
+            return (
+                StatusCode::FORBIDDEN,
+                "Only the creator can start the tournament.",
+            )
+                .into_response();
+        }
+        if let Err(e) = tournament.bracket.seed() {
+            return (StatusCode::BAD_REQUEST, e).into_response();
+        }
+    }
+    spawn_tables(&state, id);
+    StatusCode::OK.into_response()
+}
+
+/// Sends the bracket right away and again every time it changes
+async fn tournament_feed(
+    State(state): State<SharedState>,
+    ws: WebSocketUpgrade,
+    Path(id): Path<Uuid>,
+) -> Response {
+    let state = state.lock();
+    let (data, updates) = match state.tournaments.get(&id) {
+        Some(t) => (t.bracket.data(id).ser(), t.updates.subscribe()),
+        None => return (StatusCode::NOT_FOUND, "Tournament doesn't exist").into_response(),
+    };
+    let closing = state.closing.subscribe();
+    ws.on_upgrade(move |socket| handle_feed(socket, data, updates, closing))
+}
+
+async fn handle_feed(
+    mut socket: WebSocket,
+    data: String,
+    mut updates: broadcast::Receiver<String>,
+    mut closing: Closing,
+) {
+    if socket.send(Message::Text(data)).await.is_err() {
+        return;
+    }
+    loop {
+        tokio::select! {
+            update = updates.recv() => match update {
+                Ok(data) => {
+                    if socket.send(Message::Text(data)).await.is_err() {
+                        return;
+                    }
+                }
+                // Only the latest bracket matters
+                Err(broadcast::error::RecvError::Lagged(_)) => (),
+                Err(broadcast::error::RecvError::Closed) => return,
+            },
+            msg = socket.recv() => match msg {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                Some(Ok(_)) => (),
+            },
+            _ = shutdown::closing(&mut closing) => {
+                close(&mut socket, CLOSE_GOING_AWAY, "Server is shutting down").await;
+                return;
+            }
+        }
+    }
+}
+
+pub fn routes() -> Router<SharedState> {
+    Router::new()
+        .route("/", post(tournaments_create))
+        .route("/:id", get(tournament_view))
+        .route("/:id/join", post(tournament_join))
+        .route("/:id/start", post(tournament_start))
+        .route("/:id/feed", get(tournament_feed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(id: usize) -> Arc<User> {
+        Arc::new(User {
+            id,
+            name: format!("player {id}"),
+            ..User::new_empty()
+        })
+    }
+
+    fn bracket(players: usize, table_size: usize) -> Bracket {
+        let mut bracket = Bracket::new("office".into(), table_size);
+        for id in 0..players {
+            bracket.join(user(id)).unwrap();
+        }
+        bracket.seed().unwrap();
+        bracket
+    }
+
+    /// Lets the first player of every open table in the latest round win
+    fn play_round(bracket: &mut Bracket) -> bool {
+        let round = bracket.rounds.len() - 1;
+        let winners: Vec<(usize, usize)> = bracket.rounds[round]
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| t.winner.is_none())
+            .map(|(i, t)| (i, t.players[0].id))
+            .collect();
+        winners.into_iter().fold(false, |_, (table, winner)| {
+            bracket.record(round, table, winner)
+        })
+    }
+
+    #[test]
+    fn test_join() {
+        let mut bracket = Bracket::new("office".into(), 4);
+        bracket.join(user(0)).unwrap();
+        assert!(bracket.join(user(0)).is_err());
+        assert!(bracket.seed().is_err());
+        bracket.join(user(1)).unwrap();
+        bracket.seed().unwrap();
+        assert!(bracket.join(user(2)).is_err());
+        assert!(bracket.seed().is_err());
+    }
+
+    #[test]
+    fn test_seed_tables() {
+        let bracket = bracket(10, 4);
+        let sizes: Vec<usize> = bracket.rounds[0].iter().map(|t| t.players.len()).collect();
+        assert_eq!(sizes, vec![4, 3, 3]);
+        let mut seeded: Vec<usize> = bracket.rounds[0]
+            .iter()
+            .flat_map(|t| t.players.iter().map(|u| u.id))
+            .collect();
+        seeded.sort();
+        assert_eq!(seeded, (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_bye() {
+        let bracket = bracket(3, 2);
+        let byes: Vec<&Table> = bracket.rounds[0]
+            .iter()
+            .filter(|t| t.winner.is_some())
+            .collect();
+        assert_eq!(byes.len(), 1);
+        assert_eq!(byes[0].players.len(), 1);
+    }
+
+    #[test]
+    fn test_record_ignores_strangers_and_old_rounds() {
+        let mut bracket = bracket(4, 2);
+        let outsider = 99;
+        assert!(!bracket.record(0, 0, outsider));
+        assert!(bracket.rounds[0][0].winner.is_none());
+        assert!(!bracket.record(1, 0, 0));
+        assert!(!bracket.record(0, 5, 0));
+    }
+
+    #[test]
+    fn test_advance_to_winner() {
+        let mut bracket = bracket(9, 3);
+        assert!(play_round(&mut bracket));
+        assert_eq!(bracket.rounds.len(), 2);
+        assert_eq!(bracket.rounds[1].len(), 1);
+        assert_eq!(bracket.rounds[1][0].players.len(), 3);
+        assert!(!play_round(&mut bracket));
+        let winner = bracket.winner.clone().unwrap();
+        assert_eq!(winner.id, bracket.rounds[1][0].players[0].id);
+        let data = bracket.data(Uuid::nil());
+        assert_eq!(data.winner, Some(winner.name.clone()));
+        assert_eq!(data.rounds.len(), 2);
+    }
+
+    #[test]
+    fn test_abandoned_tables_drop_out() {
+        let mut bracket = bracket(6, 2);
+        assert!(!bracket.abandon(0, 0));
+        assert!(!bracket.record(0, 0, bracket.rounds[0][0].players[0].id));
+        assert!(bracket.rounds[0][0].winner.is_none());
+        assert!(!bracket.record(0, 1, bracket.rounds[0][1].players[0].id));
+        assert!(bracket.record(0, 2, bracket.rounds[0][2].players[0].id));
+        assert_eq!(bracket.rounds[1].len(), 1);
+        assert_eq!(bracket.rounds[1][0].players.len(), 2);
+        assert!(!bracket.finished());
+    }
+
+    #[test]
+    fn test_last_table_standing_wins() {
+        let mut bracket = bracket(4, 2);
+        assert!(!bracket.abandon(0, 0));
+        let winner = bracket.rounds[0][1].players[1].id;
+        assert!(!bracket.record(0, 1, winner));
+        assert_eq!(bracket.winner.as_ref().map(|u| u.id), Some(winner));
+        assert!(bracket.finished());
+    }
+
+    #[test]
+    fn test_every_table_abandoned() {
+        let mut bracket = bracket(4, 2);
+        assert!(!bracket.abandon(0, 0));
+        assert!(!bracket.finished());
+        assert!(!bracket.abandon(0, 1));
+        assert!(bracket.finished());
+        assert!(bracket.winner.is_none());
+        let data = bracket.data(Uuid::nil());
+        assert!(data.finished);
+        assert!(data.rounds[0].iter().all(|t| t.abandoned));
+    }
+
+    fn create(name: &str, table_size: usize) -> CreateTournamentData {
+        CreateTournamentData {
+            name: name.into(),
+            table_size,
+            deck: None,
+            seven_o: false,
+            jump_in: false,
+            mode: GameMode::Classic,
+        }
+    }
+
+    fn errors(input: &CreateTournamentData) -> Vec<FieldError> {
+        let decks = DeckSpec::presets().into_iter().collect();
+        input
+            .validate(&LobbyLimits::default(), &decks)
+            .err()
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn test_validate_uses_lobby_rules() {
+        assert!(errors(&create("Office cup", 4)).is_empty());
+        assert_eq!(
+            errors(&create("<b>cup</b>", 4)),
+            vec![FieldError::new(
+                "name",
+                "Tournament name can only have letters, numbers, spaces and - _ ' ! ? . #"
+            )]
+        );
+        assert_eq!(
+            errors(&create(&"a".repeat(33), 4)),
+            vec![FieldError::new(
+                "name",
+                "Tournament name can be at most 32 characters."
+            )]
+        );
+        assert_eq!(
+            errors(&create("Office cup", usize::MAX)),
+            vec![
+                FieldError::new("table_size", "Games need 2 to 10 players."),
+                FieldError::new("deck", "Deck is too small for that many players."),
+            ]
+        );
+        let teams = CreateTournamentData {
+            mode: GameMode::Teams,
+            ..create("Office cup", 4)
+        };
+        assert_eq!(
+            errors(&teams),
+            vec![FieldError::new(
+                "mode",
+                "Tournament tables can't be played in teams."
+            )]
+        );
+    }
+}
@@ -1,5 +1,7 @@
+use std::net::SocketAddr;
+
 use axum::{
-    extract::State,
+    extract::{ConnectInfo, State},
     http::StatusCode,
     routing::{get, post},
     Json, Router,
@@ -54,16 +56,21 @@ pub struct UserCreate {
 }
 async fn login(
     jar: CookieJar,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
     State(state): State<SharedState>,
     Json(input): Json<UserCreate>,
 ) -> Result<CookieJar, (StatusCode, &'static str)> {
-    if let Some(id) = state.lock().new_user(input) {
+    let mut state = state.lock();
+    if !state.login_limiter.try_take(addr.ip()) {
+        return Err((StatusCode::TOO_MANY_REQUESTS, "Too many login attempts"));
+    }
+    if let Some(id) = state.new_user(input) {
         let cookie = Cookie::build((SESSION_TOKEN, id.to_string()))
             .expires(Expiration::Session)
             .http_only(false)
             .same_site(SameSite::Lax)
             .path("/")
-            .secure(false);
+            .secure(state.secure_cookies);
 
         Ok(jar.add(cookie))
     } else {
@@ -0,0 +1,239 @@
+use std::path::Path;
+
+use axum::Router;
+use tower_http::compression::CompressionLayer;
+use tracing::info;
+
+use crate::config::Config;
+
+/// Files under `assets/` have a hash in their name, so browsers can keep them forever
+const ASSETS_CACHE: &str = "public, max-age=31536000, immutable";
+/// Everything else, `index.html` most of all, is checked with the server on every load
+const PAGE_CACHE: &str = "no-cache";
+
+/// Serves the built web client. Paths without a file get `index.html` so the client's router
+/// can handle them
+pub fn client(config: &Config) -> Router {
+    let router = files(&config.paths.web);
+    if config.compress {
+        router.layer(CompressionLayer::new())
+    } else {
+        router
+    }
+}
+
+#[cfg(not(feature = "embed"))]
+fn files(dir: &Path) -> Router {
+    use axum::http::{header::CACHE_CONTROL, HeaderValue, Response};
+    use tower_http::{
+        services::{ServeDir, ServeFile},
+        set_header::SetResponseHeader,
+    };
+    use tracing::warn;
+
+    /// Only successful responses get cached, a missing asset might show up in the next deploy
+    fn cache_control<B>(
+        value: &'static str,
+    ) -> impl FnMut(&Response<B>) -> Option<HeaderValue> + Clone {
+        move |res| {
+            res.status()
+                .is_success()
+                .then(|| HeaderValue::from_static(value))
+        }
+    }
+
+    if !dir.is_dir() {
+        warn!(
+            "No web client in {}, build it to serve it from here",
+            dir.display()
+        );
+        return Router::new();
+    }
+    info!("Serving the web client from {}", dir.display());
+    let assets = ServeDir::new(dir.join("assets"))
+        .precompressed_br()
+        .precompressed_gzip();
+    let pages = ServeDir::new(dir)
+        .precompressed_br()
+        .precompressed_gzip()
+        .fallback(ServeFile::new(dir.join("index.html")));
+    Router::new()
+        .nest_service(
+            "/assets",
+            SetResponseHeader::overriding(assets, CACHE_CONTROL, cache_control(ASSETS_CACHE)),
+        )
+        .fallback_service(SetResponseHeader::overriding(
+            pages,
+            CACHE_CONTROL,
+            cache_control(PAGE_CACHE),
+        ))
+}
+
+#[cfg(feature = "embed")]
+fn files(_dir: &Path) -> Router {
+    info!("Serving the web client built into the binary");
+    Router::new().fallback(embedded::serve)
+}
+
+#[cfg(feature = "embed")]
+mod embedded {
+    use axum::{
+        http::{
+            header::{CACHE_CONTROL, CONTENT_TYPE, ETAG, IF_NONE_MATCH},
+            HeaderMap, StatusCode, Uri,
+        },
+        response::{IntoResponse, Response},
+    };
+    use rust_embed::RustEmbed;
+
+    use super::{ASSETS_CACHE, PAGE_CACHE};
+
+    #[derive(RustEmbed)]
+    #[folder = "web/dist"]
+    struct Assets;
+
+    pub async fn serve(uri: Uri, headers: HeaderMap) -> Response {
+        let path = uri.path().trim_start_matches('/');
+        let (path, file) = match Assets::get(path) {
+            Some(file) => (path, file),
+            None if path.starts_with("assets/") => return StatusCode::NOT_FOUND.into_response(),
+            None => match Assets::get("index.html") {
+                Some(file) => ("index.html", file),
+                None => return StatusCode::NOT_FOUND.into_response(),
+            },
+        };
+        let hash: String = file
+            .metadata
+            .sha256_hash()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        let etag = format!("\"{hash}\"");
+        let cache = if path.starts_with("assets/") {
+            ASSETS_CACHE
+        } else {
+            PAGE_CACHE
+        };
+        let headers_out = [(ETAG, etag.clone()), (CACHE_CONTROL, cache.into())];
+        if headers
+            .get(IF_NONE_MATCH)
+            .is_some_and(|v| v == etag.as_str())
+        {
+            return (StatusCode::NOT_MODIFIED, headers_out).into_response();
+        }
+        let content_type = [(CONTENT_TYPE, file.metadata.mimetype().to_string())];
+        (headers_out, content_type, file.data).into_response()
+    }
+}
+
+#[cfg(all(test, not(feature = "embed")))]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use axum::{
+        body::{to_bytes, Body},
+        http::{
+            header::{ACCEPT_ENCODING, CACHE_CONTROL, CONTENT_ENCODING},
+            Request, StatusCode,
+        },
+        response::Response,
+    };
+    use tower::ServiceExt;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::{app, AppState, SharedState};
+
+    /// A built client in a temporary directory, removed when dropped
+    struct Dist(PathBuf);
+
+    impl Dist {
+        fn new() -> Self {
+            let dir = std::env::temp_dir().join(Uuid::new_v4().to_string());
+            fs::create_dir_all(dir.join("assets")).unwrap();
+            fs::write(dir.join("index.html"), "<div id=root></div>").unwrap();
+            fs::write(
+                dir.join("assets/index-1a2b3c.js"),
+                "console.log(1);".repeat(100),
+            )
+            .unwrap();
+            Self(dir)
+        }
+
+        fn app(&self, compress: bool) -> Router {
+            let mut config = Config {
+                compress,
+                ..Default::default()
+            };
+            config.paths.web = self.0.clone();
+            app(
+                SharedState::new(AppState::default().into()),
+                client(&config),
+            )
+        }
+    }
+
+    impl Drop for Dist {
+        fn drop(&mut self) {
+            fs::remove_dir_all(&self.0).ok();
+        }
+    }
+
+    async fn get(app: Router, path: &str) -> Response {
+        let request = Request::get(path)
+            .header(ACCEPT_ENCODING, "gzip")
+            .body(Body::empty())
+            .unwrap();
+        app.oneshot(request).await.unwrap()
+    }
+
+    async fn text(res: Response) -> String {
+        String::from_utf8(
+            to_bytes(res.into_body(), usize::MAX)
+                .await
+                .unwrap()
+                .to_vec(),
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_client_routes_get_index() {
+        let dist = Dist::new();
+        for path in ["/", "/lobbies", "/lobbies/play/123"] {
+            let res = get(dist.app(false), path).await;
+            assert_eq!(res.status(), StatusCode::OK, "{path}");
+            assert_eq!(res.headers()[CACHE_CONTROL], PAGE_CACHE);
+            assert_eq!(text(res).await, "<div id=root></div>");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_assets_are_cached() {
+        let dist = Dist::new();
+        let res = get(dist.app(false), "/assets/index-1a2b3c.js").await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[CACHE_CONTROL], ASSETS_CACHE);
+        let res = get(dist.app(false), "/assets/missing.js").await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        assert!(!res.headers().contains_key(CACHE_CONTROL));
+    }
+
+    #[tokio::test]
+    async fn test_unknown_api_paths_are_not_found() {
+        let dist = Dist::new();
+        let res = get(dist.app(false), "/api/nothing").await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        let res = get(dist.app(false), "/api/lobbies").await;
+        assert_eq!(text(res).await, r#"{"lobbies":[],"next":null}"#);
+    }
+
+    #[tokio::test]
+    async fn test_compression() {
+        let dist = Dist::new();
+        let res = get(dist.app(true), "/assets/index-1a2b3c.js").await;
+        assert_eq!(res.headers()[CONTENT_ENCODING], "gzip");
+        let res = get(dist.app(false), "/assets/index-1a2b3c.js").await;
+        assert!(!res.headers().contains_key(CONTENT_ENCODING));
+    }
+}
@@ -3,7 +3,7 @@ import type { NormalCardKind } from '@bindings/NormalCardKind';
 import type { SpecialCardKind } from '@bindings/SpecialCardKind';
 import { COLOR_TO_BG } from '../util';
 
-import { NoSymbolIcon, ArrowPathIcon } from "@heroicons/react/24/outline"
+import { NoSymbolIcon, ArrowPathIcon, ArrowsRightLeftIcon } from "@heroicons/react/24/outline"
 import { twJoin } from 'tailwind-merge';
 
 
@@ -14,12 +14,16 @@ function NormalCardInner({ kind }: { kind: NormalCardKind }) {
 
         case "PlusTwo": return <span>+2</span>
         case "Number": return <span>{kind.fields}</span>
+        case "SkipAll": return <span className='flex flex-col items-center text-xl'><NoSymbolIcon className='w-10 h-10 stroke-2' />ALL</span>
+        case "DiscardAll": return <span className='text-xl'>DISCARD ALL</span>
     }
 }
 function SpecialCardInner({ kind }: { kind: SpecialCardKind }) {
     switch (kind) {
         case "ChangeColor": return <></>
         case "PlusFour": return <span>+4</span>
+        case "DrawColor": return <span>+?</span>
+        case "SwapHands": return <ArrowsRightLeftIcon className='w-14 h-14 stroke-2' />
     }
 }
 
@@ -1,11 +1,13 @@
 import { PlayerInfo } from "@bindings/PlayerInfo";
 import PlayerInfoView from "./PlayerInfoView";
 
-export default function PlayerCarousel({ playerInfos, selected }: { playerInfos: PlayerInfo[], selected: number }) {
+export default function PlayerCarousel({ playerInfos, selected, onSelect }: { playerInfos: PlayerInfo[], selected: number, onSelect?: (index: number) => void }) {
 
     return (
         <div className='flex flex-row gap-4'>
-            {playerInfos.map((p, i) => <PlayerInfoView selected={i == selected} playerInfo={p} key={i} />)}
+            {playerInfos.map((p, i) => <div key={i} onClick={() => onSelect?.(i)} className={onSelect && 'cursor-pointer'}>
+                <PlayerInfoView selected={i == selected} playerInfo={p} />
+            </div>)}
         </div>
     )
 }
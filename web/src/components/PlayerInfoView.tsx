@@ -9,13 +9,15 @@ import Avatar from "./Avatar";
 import { twJoin } from "tailwind-merge";
 
 export default function PlayerInfoView({ playerInfo, selected = false }: { playerInfo: PlayerInfo, selected?: boolean }) {
-    return (<div>
+    return (<div className={twJoin((!playerInfo.connected || playerInfo.eliminated) && "opacity-50")}>
         <Avatar
             {...playerInfo.user.avatar}
         />
         <UICard className={twJoin(selected && "ring-2 ring-offset-2 ring-offset-zinc-900 ring-white/50")}>
-            <UICard.Header>{playerInfo.user.name}</UICard.Header>
+            <UICard.Header>{playerInfo.user.name}{playerInfo.team !== null && ` (Team ${playerInfo.team + 1})`}</UICard.Header>
             <UICard.Body>Cards: {playerInfo.cardCount}</UICard.Body>
+            <UICard.Body>{playerInfo.connected ? `${playerInfo.latencyMs ?? "?"} ms` : "Disconnected"}</UICard.Body>
+            {playerInfo.eliminated && <UICard.Body>Spectating</UICard.Body>}
         </UICard>
 
     </div>
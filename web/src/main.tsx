@@ -11,6 +11,7 @@ import {
 import Lobbies from './routes/Lobbies.tsx';
 import LoginPage from './routes/Login.tsx'
 import CardsPreview from './routes/CardsPreview.tsx'
+import Tournament from './routes/Tournament.tsx'
 
 
 
@@ -26,6 +27,9 @@ const router = createBrowserRouter([
     }, {
       path: "lobbies/play/:lobbyId",
       Component: Game,
+    }, {
+      path: "tournaments/:tournamentId",
+      Component: Tournament,
     }, {
       path: "login",
       Component: LoginPage,
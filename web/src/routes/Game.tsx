@@ -2,9 +2,9 @@ import CardView, { UnplayedCardView } from '../components/CardView.tsx'
 import { useState, } from 'react'
 import ChatBox from '../components/ChatBox.tsx'
 import RadialColorSelector from '../components/RadialColorSelector.tsx';
-import { useParams } from 'react-router-dom';
+import { useLocation, useParams } from 'react-router-dom';
 import CardPile from '../components/CardPile.tsx';
-import useGame, { canPlayPlannedPlay, plannedPlayToArr } from '../useGame.ts';
+import useGame, { canJumpIn, canPlayPlannedPlay, plannedPlayToArr } from '../useGame.ts';
 import Button from '../components/Button.tsx';
 import { twJoin } from 'tailwind-merge';
 import PlayerCarousel from '../components/PlayerCarousel.tsx';
@@ -13,7 +13,12 @@ import PlayerCarousel from '../components/PlayerCarousel.tsx';
 export default function Game() {
   const [selection, setSelection] = useState<number | null>(null);
   const { lobbyId } = useParams();
-  const { dispatch, sendChatMessage, playCards, takeCard, state } = useGame(lobbyId!);
+  const password: string | null = useLocation().state?.password ?? null;
+  const { dispatch, sendChatMessage, playCards, takeCard, chooseSwapTarget, jumpIn, giveToPartner, state } = useGame(lobbyId!, password);
+  const showJumpIn = state.rules?.jumpIn && state.turnIndex !== state.selfIndex && selection !== null
+    && canJumpIn(state.topCard, state.ownCards[selection]);
+  const showGiveToPartner = state.rules?.partnerExchange && selection !== null && state.ownCards.length > 1;
+  const choosingSwapTarget = state.awaitingSwapTarget && state.turnIndex === state.selfIndex;
   const cardPileEnabled = selection !== null && canPlayPlannedPlay(state, selection);
 
   const showColorSelector = state.ownCards[selection!]?.kind.tag === "Special" && cardPileEnabled;
@@ -27,7 +32,9 @@ export default function Game() {
           i == state.selfIndex ?
             { ...p, user: { ...p.user, name: `You (${p.user.name})`, } }
             : p
-        )} selected={state.turnIndex} />
+        )} selected={state.plannedPlay?.tag === "Special" && state.plannedPlay.target !== undefined ? state.plannedPlay.target : state.turnIndex}
+          onSelect={(i) => choosingSwapTarget ? chooseSwapTarget(i) : dispatch({ type: "set_target", target: i })} />
+        {choosingSwapTarget && <span>Choose a player to swap hands with</span>}
         <div className='relative flex z-0 grid grid-cols-2 gap-2'>
           <div
             className="z-10 p-2 border-zinc-500 border-dashed rounded-lg border-2 grid grid-cols-subgrid col-span-2">
@@ -45,6 +52,10 @@ export default function Game() {
 
           <Button onClick={playCards} variant='green' className='w-16 justify-self-start'>Play </Button>
 
+          {showJumpIn && <Button onClick={() => { jumpIn(selection!); setSelection(null) }} variant='green' className='col-span-2'>Jump in!</Button>}
+
+          {showGiveToPartner && <Button onClick={() => { giveToPartner(selection!); setSelection(null) }} variant='green' className='col-span-2'>Give to partner</Button>}
+
           {showColorSelector && <div className='z-20 absolute top-1/2 left-3/4 -translate-x-1/2 -translate-y-3/4'>
             <RadialColorSelector onClick={(c) => {
               if (selection !== null) {
@@ -1,24 +1,80 @@
 import { useState, useEffect } from 'react'
 import type { LobbyData } from '@bindings/LobbyData'
+import type { LobbyPage } from '@bindings/LobbyPage'
+import type { LobbySort } from '@bindings/LobbySort'
+import type { LobbyEvent } from '@bindings/LobbyEvent'
 import type { CreateLobbyData } from '@bindings/CreateLobbyData'
+import type { CreatedLobby } from '@bindings/CreatedLobby'
+import type { FieldError } from '@bindings/FieldError'
+import type { ValidationErrors } from '@bindings/ValidationErrors'
+import type { GameMode } from '@bindings/GameMode'
+import type { CreateTournamentData } from '@bindings/CreateTournamentData'
+import type { QueueData } from '@bindings/QueueData'
+import type { MatchmakingEvent } from '@bindings/MatchmakingEvent'
+import useWebSocket from 'react-use-websocket';
 import { Link, useNavigate } from 'react-router-dom';
 
 import { UserGroupIcon } from '@heroicons/react/24/outline'
 import UICard from '../components/UICard';
+import { socketUrl } from '../util';
 
 export default function Lobbies() {
     const [lobbies, setLobbies] = useState<LobbyData[]>([]);
+    const [next, setNext] = useState<string | null>(null);
+    const [search, setSearch] = useState("");
+    const [openOnly, setOpenOnly] = useState(false);
+    const [sort, setSort] = useState<LobbySort>("name");
+    const [createErrors, setCreateErrors] = useState<FieldError[]>([]);
     const navigate = useNavigate();
+    const [queue, setQueue] = useState<QueueData | null>(null);
+    // The socket has to be open before joining the queue, closing it leaves the queue
+    useWebSocket(queue === null ? null : socketUrl("/matchmaking/events"), {
+        onOpen: () => joinQueue(queue!),
+        onMessage: (message) => {
+            const event: MatchmakingEvent = JSON.parse(message.data);
+            if (event.tag === "Matched") {
+                setQueue(null);
+                navigate(`play/${event.fields.lobby}`);
+            }
+        },
+    });
 
-    async function fetchLobbies() {
-        const response = await fetch("/api/lobbies");
-        const lobbies: LobbyData[] = await response.json();
-        console.log(lobbies);
-        setLobbies(lobbies);
+    async function fetchLobbies(cursor: string | null) {
+        const params = new URLSearchParams({ sort });
+        if (search !== "") params.set("name", search);
+        if (openOnly) params.set("open", "true");
+        if (cursor !== null) params.set("cursor", cursor);
+        const response = await fetch(`/api/lobbies?${params}`);
+        const page: LobbyPage = await response.json();
+        setLobbies(cursor === null ? page.lobbies : [...lobbies, ...page.lobbies]);
+        setNext(page.next);
     }
     useEffect(() => {
-        fetchLobbies()
-    }, []);
+        fetchLobbies(null)
+        // Updates only need patching in, anything else can change what matches the filters
+        const stream = new EventSource("/api/lobbies/stream");
+        stream.onmessage = (message) => {
+            const event: LobbyEvent = JSON.parse(message.data);
+            switch (event.tag) {
+                case "Updated":
+                    setLobbies(lobbies => lobbies.map(l => l.id === event.fields.id ? event.fields : l));
+                    break;
+                case "Removed":
+                    setLobbies(lobbies => lobbies.filter(l => l.id !== event.fields.id));
+                    break;
+                default:
+                    fetchLobbies(null);
+            }
+        };
+        return () => stream.close();
+        // eslint-disable-next-line react-hooks/exhaustive-deps
+    }, [search, openOnly, sort]);
+    function rulesSummary(lobby: LobbyData) {
+        const rules = [lobby.rules.mode, `${lobby.rules.deck} deck`];
+        if (lobby.rules.sevenO) rules.push("Seven-O");
+        if (lobby.rules.jumpIn) rules.push("Jump-in");
+        return rules.join(", ");
+    }
     async function createLobby(data: CreateLobbyData) {
         const response = await fetch("/api/lobbies", {
             method: "POST", headers: {
@@ -27,24 +83,106 @@ export default function Lobbies() {
         });
         console.log(response);
         if (response.ok) {
-            navigate(`play/${await response.json()}`);
+            const lobby: CreatedLobby = await response.json();
+            if (data.private) {
+                alert(`Invite code: ${lobby.code}`);
+            }
+            join(lobby.id, data.password);
+        } else if (response.status === 400) {
+            const body: ValidationErrors = await response.json();
+            setCreateErrors(body.errors);
         } else {
             alert(await response.text())
         }
 
     }
+    async function joinQueue(data: QueueData) {
+        const response = await fetch("/api/matchmaking/queue", {
+            method: "POST", headers: {
+                'Content-Type': 'application/json',
+            }, body: JSON.stringify(data)
+        });
+        if (!response.ok) {
+            setQueue(null);
+            alert(await errorText(response))
+        }
+    }
+    function onSubmitQueue(event: React.FormEvent<HTMLFormElement>) {
+        event.preventDefault();
+        const formData = new FormData(event.currentTarget);
+        setQueue({ players: Number(formData.get("players")), deck: formData.get("deck") as string, seven_o: false, jump_in: false, mode: "Classic" })
+    }
+    async function createTournament(data: CreateTournamentData) {
+        const response = await fetch("/api/tournaments", {
+            method: "POST", headers: {
+                'Content-Type': 'application/json',
+            }, body: JSON.stringify(data)
+        });
+        if (response.ok) {
+            navigate(`/tournaments/${await response.json()}`);
+        } else {
+            alert(await errorText(response))
+        }
+    }
+    // Validation failures come as field errors, everything else as plain text
+    async function errorText(response: Response) {
+        if (response.status === 400) {
+            const body: ValidationErrors = await response.json();
+            return body.errors.map(e => e.message).join("\n");
+        }
+        return await response.text();
+    }
+    function onSubmitTournament(event: React.FormEvent<HTMLFormElement>) {
+        event.preventDefault();
+        const formData = new FormData(event.currentTarget);
+        createTournament({ name: formData.get("name") as string, table_size: Number(formData.get("table_size")), deck: formData.get("deck") as string, seven_o: false, jump_in: false, mode: formData.get("mode") as GameMode })
+    }
+    function errorsFor(field: string | null) {
+        return createErrors.filter(e => e.field === field).map((e, i) => <div key={i} className='text-red-400 text-sm'>{e.message}</div>);
+    }
+    // The password goes in the route state so it never shows up in a URL
+    function join(id: string, password: string | null) {
+        navigate(`play/${id}`, { state: { password } });
+    }
+    function joinLocked(lobby: LobbyData) {
+        const password = prompt("Password");
+        if (password !== null) {
+            join(lobby.id, password);
+        }
+    }
+    async function joinByCode(event: React.FormEvent<HTMLFormElement>) {
+        event.preventDefault();
+        const code = new FormData(event.currentTarget).get("code") as string;
+        const response = await fetch(`/api/lobbies/code/${encodeURIComponent(code)}`);
+        if (!response.ok) {
+            alert(await response.text());
+            return;
+        }
+        navigate(joinPath(await response.json(), prompt("Password, leave empty if there is none") || null));
+    }
     function onSubmit(event: React.FormEvent<HTMLFormElement>) {
         event.preventDefault();
         const formData = new FormData(event.currentTarget);
-        createLobby({ name: formData.get("name") as string, max_players: Number(formData.get("max_players")) })
+        createLobby({ name: formData.get("name") as string, max_players: Number(formData.get("max_players")), deck: formData.get("deck") as string, seven_o: formData.get("seven_o") !== null, jump_in: formData.get("jump_in") !== null, mode: formData.get("mode") as GameMode, partner_exchange: formData.get("partner_exchange") !== null, private: formData.get("private") !== null, password: (formData.get("password") as string) || null })
     }
 
     return <div className=' w-full h-full flex flex-row gap-4'>
         <div className='overflow-y-auto h-full border-zinc-700 border bg-zinc-900'>
+            <div className='flex flex-row gap-2 p-2'>
+                <input type='text' placeholder='Search' className='bg-zinc-800 border border-zinc-700 h-8' value={search} onChange={e => setSearch(e.target.value)} />
+                <label className='flex flex-row gap-2 items-center'><input type='checkbox' checked={openOnly} onChange={e => setOpenOnly(e.target.checked)} />Open seats</label>
+                <select value={sort} onChange={e => setSort(e.target.value as LobbySort)} className='bg-zinc-800 border border-zinc-700 h-8'>
+                    <option value='name'>Name</option>
+                    <option value='players'>Fullest</option>
+                    <option value='open_seats'>Emptiest</option>
+                </select>
+            </div>
             <table className='border-collapse table-fixed text-lg w-full'>
                 <thead className='sticky top-0'>
                     <tr>
                         <td className='border-zinc-700 bg-zinc-800 py-2 pl-2'>Name</td>
+                        <td className=' border-zinc-700 bg-zinc-800 py-2 pl-2'>Owner</td>
+                        <td className=' border-zinc-700 bg-zinc-800 py-2 pl-2'>Rules</td>
                         <td className=' border-zinc-700 bg-zinc-800 py-2 pl-2'>Players</td>
                     </tr>
                 </thead>
@@ -52,11 +190,14 @@ export default function Lobbies() {
                     {lobbies.map((lobby, i) =>
                         <tr key={i}>
                             <td className='border-y border-zinc-700 py-2 pl-2'>{lobby.name}</td>
-                            <td className='border-y border-zinc-700 py-2 pl-2'>{lobby.players}/{lobby.max_players} <Link to={`play/${lobby.id}`}> Join </Link> </td>
+                            <td className='border-y border-zinc-700 py-2 pl-2'>{lobby.owner}</td>
+                            <td className='border-y border-zinc-700 py-2 pl-2'>{rulesSummary(lobby)}</td>
+                            <td className='border-y border-zinc-700 py-2 pl-2'>{lobby.players}/{lobby.max_players} {lobby.game_started ? "Started" : lobby.locked ? <button onClick={() => joinLocked(lobby)}> Join (password) </button> : <Link to={`play/${lobby.id}`}> Join </Link>} </td>
                         </tr>
                     )}
                 </tbody>
             </table>
+            {next !== null && <button className='w-full h-8 bg-zinc-800 border-t border-zinc-700' onClick={() => fetchLobbies(next)}>Load more</button>}
         </div>
         <div className='self-start basis-1/5 flex-none'>
             <UICard>
@@ -64,12 +205,89 @@ export default function Lobbies() {
                     Create Lobby
                 </UICard.Header>
                 <form className='text-white flex flex-col gap-2 p-2' onSubmit={onSubmit}>
-                    <input type='text' placeholder='Lobby name' className='bg-zinc-800 border border-zinc-700 h-8' name='name'></input>
+                    {errorsFor(null)}
+                    <input type='text' placeholder='Lobby name' maxLength={32} className='bg-zinc-800 border border-zinc-700 h-8' name='name'></input>
+                    {errorsFor("name")}
 
                     <div className='flex flex-row bg-zinc-800 border border-zinc-700'>
                         <UserGroupIcon className='w-8 h-8 float-left' />
-                        <input type='number' defaultValue={4} max={8} min={2} className='bg-inherit w-full' name='max_players' />
+                        <input type='number' defaultValue={4} max={10} min={2} className='bg-inherit w-full' name='max_players' />
+                    </div>
+                    {errorsFor("max_players")}
+                    <select name='deck' defaultValue='classic' className='bg-zinc-800 border border-zinc-700 h-8'>
+                        <option value='classic'>Classic deck</option>
+                        <option value='small'>Small deck</option>
+                        <option value='double'>Double deck</option>
+                        <option value='party'>Party deck</option>
+                    </select>
+                    {errorsFor("deck")}
+                    <select name='mode' defaultValue='Classic' className='bg-zinc-800 border border-zinc-700 h-8'>
+                        <option value='Classic'>Classic</option>
+                        <option value='Teams'>Teams (4 or 6 players, /team to switch)</option>
+                        <option value='Elimination'>Elimination</option>
+                    </select>
+                    <label className='flex flex-row gap-2'><input type='checkbox' name='seven_o' />Seven-O rule</label>
+                    <label className='flex flex-row gap-2'><input type='checkbox' name='jump_in' />Jump-in rule</label>
+                    <label className='flex flex-row gap-2'><input type='checkbox' name='partner_exchange' />Partners exchange a card</label>
+                    {errorsFor("partner_exchange")}
+                    <label className='flex flex-row gap-2'><input type='checkbox' name='private' />Private, join with the invite code</label>
+                    <input type='password' placeholder='Password (optional)' className='bg-zinc-800 border border-zinc-700 h-8' name='password'></input>
+                    {errorsFor("password")}
+                    <input type='submit' value='Create' className=' w-full h-8 bg-green-500 border  border-green-400' />
+                </form>
+            </UICard>
+            <UICard className='mt-4'>
+                <UICard.Header>
+                    Join with Code
+                </UICard.Header>
+                <form className='text-white flex flex-row gap-2 p-2' onSubmit={joinByCode}>
+                    <input type='text' placeholder='Invite code' className='bg-zinc-800 border border-zinc-700 h-8 w-full uppercase' name='code'></input>
+                    <input type='submit' value='Join' className='h-8 px-2 bg-green-500 border border-green-400' />
+                </form>
+            </UICard>
+            <UICard className='mt-4'>
+                <UICard.Header>
+                    Quick Play
+                </UICard.Header>
+                {queue === null ?
+                    <form className='text-white flex flex-col gap-2 p-2' onSubmit={onSubmitQueue}>
+                        <div className='flex flex-row bg-zinc-800 border border-zinc-700'>
+                            <UserGroupIcon className='w-8 h-8 float-left' />
+                            <input type='number' defaultValue={4} max={8} min={2} className='bg-inherit w-full' name='players' />
+                        </div>
+                        <select name='deck' defaultValue='classic' className='bg-zinc-800 border border-zinc-700 h-8'>
+                            <option value='classic'>Classic deck</option>
+                            <option value='small'>Small deck</option>
+                            <option value='double'>Double deck</option>
+                            <option value='party'>Party deck</option>
+                        </select>
+                        <input type='submit' value='Find a game' className=' w-full h-8 bg-green-500 border  border-green-400' />
+                    </form> :
+                    <div className='flex flex-col gap-2 p-2'>
+                        Waiting for {queue.players - 1} other players...
+                        <button className='w-full h-8 bg-zinc-800 border border-zinc-700' onClick={() => setQueue(null)}>Cancel</button>
+                    </div>}
+            </UICard>
+            <UICard className='mt-4'>
+                <UICard.Header>
+                    Create Tournament
+                </UICard.Header>
+                <form className='text-white flex flex-col gap-2 p-2' onSubmit={onSubmitTournament}>
+                    <input type='text' placeholder='Tournament name' className='bg-zinc-800 border border-zinc-700 h-8' name='name'></input>
+                    <div className='flex flex-row bg-zinc-800 border border-zinc-700'>
+                        <UserGroupIcon className='w-8 h-8 float-left' />
+                        <input type='number' defaultValue={4} max={8} min={2} className='bg-inherit w-full' name='table_size' />
                     </div>
+                    <select name='deck' defaultValue='classic' className='bg-zinc-800 border border-zinc-700 h-8'>
+                        <option value='classic'>Classic deck</option>
+                        <option value='small'>Small deck</option>
+                        <option value='double'>Double deck</option>
+                        <option value='party'>Party deck</option>
+                    </select>
+                    <select name='mode' defaultValue='Classic' className='bg-zinc-800 border border-zinc-700 h-8'>
+                        <option value='Classic'>First one out wins the table</option>
+                        <option value='Elimination'>Last one standing wins the table</option>
+                    </select>
                     <input type='submit' value='Create' className=' w-full h-8 bg-green-500 border  border-green-400' />
                 </form>
             </UICard>
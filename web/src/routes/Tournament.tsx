@@ -0,0 +1,53 @@
+import { Link, useParams } from 'react-router-dom';
+import useWebSocket from 'react-use-websocket';
+import type { TournamentData } from '@bindings/TournamentData'
+import UICard from '../components/UICard';
+import { socketUrl } from '../util';
+
+export default function Tournament() {
+    const { tournamentId } = useParams();
+    const { lastJsonMessage } = useWebSocket(socketUrl(`/tournaments/${tournamentId}/feed`));
+    const tournament = lastJsonMessage as TournamentData | null;
+
+    async function post(action: "join" | "start") {
+        const response = await fetch(`/api/tournaments/${tournamentId}/${action}`, { method: "POST" });
+        if (!response.ok) {
+            alert(await response.text())
+        }
+    }
+
+    if (tournament === null) {
+        return <div>Loading...</div>
+    }
+    return <div className='w-full h-full flex flex-col gap-4'>
+        <div className='flex flex-row gap-2 items-center'>
+            <span className='text-2xl'>{tournament.name}</span>
+            {!tournament.started && <>
+                <button className='bg-green-500 border border-green-400 px-2' onClick={() => post("join")}>Join</button>
+                <button className='bg-zinc-800 border border-zinc-700 px-2' onClick={() => post("start")}>Start</button>
+            </>}
+            {tournament.winner !== null && <span className='text-yellow-400'>Winner: {tournament.winner}</span>}
+            {tournament.finished && tournament.winner === null && <span className='text-zinc-400'>Ended without a winner</span>}
+        </div>
+        {!tournament.started && <div>Players: {tournament.players.join(", ")}</div>}
+        <div className='flex flex-row gap-4 overflow-x-auto'>
+            {tournament.rounds.map((round, i) =>
+                <div key={i} className='flex flex-col gap-2 flex-none'>
+                    <div className='text-lg'>Round {i + 1}</div>
+                    {round.map((table, j) =>
+                        <UICard key={j}>
+                            <UICard.Header>Table {j + 1}</UICard.Header>
+                            <UICard.Body>
+                                {table.players.map(p => <div key={p} className={p === table.winner ? 'text-yellow-400' : ''}>{p}</div>)}
+                            </UICard.Body>
+                            {table.abandoned && <UICard.Footer>Abandoned</UICard.Footer>}
+                            {table.lobby !== null && table.winner === null && !table.abandoned && <UICard.Footer>
+                                <Link to={`/lobbies/play/${table.lobby}`}>Play</Link>
+                            </UICard.Footer>}
+                        </UICard>
+                    )}
+                </div>
+            )}
+        </div>
+    </div>
+}
@@ -4,9 +4,12 @@ import { GameState } from "@bindings/GameState"
 import { Response } from "@bindings/Response"
 import { Request } from "@bindings/Request";
 import { useCallback, useEffect, useReducer } from "react";
-import useWebSocket from "react-use-websocket";
-import { last } from "./util";
+import useWebSocket, { ReadyState } from "react-use-websocket";
+import { last, socketUrl } from "./util";
 import { Color } from "@bindings/Color";
+import { Rules } from "@bindings/Rules";
+
+export const PROTOCOL_VERSION = 1;
 
 export function plannedPlayToArr(play: PlannedPlay | null, state: State): Card[] {
     switch (play?.tag) {
@@ -42,6 +45,11 @@ export function canPlayCard(topCard: Card | null, toPlay: Card): boolean {
     return false
 }
 
+export function canJumpIn(topCard: Card | null, toPlay: Card): boolean {
+    if (topCard === null || toPlay.kind.tag === "Special" || topCard.kind.tag === "Special") return false;
+    return toPlay.color === topCard.color && JSON.stringify(toPlay.kind) === JSON.stringify(topCard.kind);
+}
+
 export function canPlayConsecutiveCard(topCard: Card | null, toPlay: Card): boolean {
     if (topCard === null) return true;
     if (toPlay.kind.tag === "Special" || topCard.kind.tag === "Special") return false;
@@ -52,10 +60,11 @@ export function canPlayConsecutiveCard(topCard: Card | null, toPlay: Card): bool
 }
 
 
-export type PlannedPlay = { tag: "Special", card: number, color: Color } | { tag: "Multiple", cards: number[] }
+export type PlannedPlay = { tag: "Special", card: number, color: Color, target?: number } | { tag: "Multiple", cards: number[] }
 export type State = {
     plannedPlay: PlannedPlay | null,
     messages: ChatMessage[],
+    rules: Rules | null,
 
 } & GameState
 
@@ -76,6 +85,12 @@ type Action = {
     color: Color,
 } | {
     type: "confirm_play"
+} | {
+    type: "set_target",
+    target: number,
+} | {
+    type: "welcome",
+    rules: Rules,
 }
 
 function reducer(state: State, action: Action): State {
@@ -90,6 +105,17 @@ function reducer(state: State, action: Action): State {
                 plannedPlay: null,
             }
         }
+        case "set_target": {
+            if (state.plannedPlay?.tag !== "Special" || action.target === state.selfIndex) return state;
+            return {
+                ...state,
+                plannedPlay: { ...state.plannedPlay, target: action.target }
+            }
+        }
+        case "welcome": return {
+            ...state,
+            rules: action.rules,
+        };
         case "clear_play": return {
             ...state,
             plannedPlay: null,
@@ -123,6 +149,22 @@ function reducer(state: State, action: Action): State {
         case "new_game_state": return {
             ...state,
             ...action.state,
+            messages: state.messages.concat(action.state.events.flatMap((e) => {
+                const name = (i: number) => action.state.users[i]?.user.name
+                switch (e.tag) {
+                    case "JumpedIn": return [`${name(e.fields.player)} jumped in!`]
+                    case "HandsSwapped": return [`${name(e.fields.player)} swapped hands with ${name(e.fields.target)}!`]
+                    case "HandsRotated": return ["Everyone passed their hand on!"]
+                    case "CardGiven": return [`${name(e.fields.player)} gave ${name(e.fields.partner)} a card.`]
+                    case "TeamChanged": return [`${name(e.fields.player)} joined team ${e.fields.team + 1}.`]
+                    case "RoundOver": return [e.fields.team === null
+                        ? `${name(e.fields.winner)} wins the round and scores ${e.fields.points} points!`
+                        : `Team ${e.fields.team + 1} wins the round and scores ${e.fields.points} points!`, "Type /start to deal the next round."]
+                    case "Eliminated": return [`${name(e.fields.player)} is out!`]
+                    case "GameOver": return [`${name(e.fields.winner)} is the last one standing!`]
+                    default: return []
+                }
+            }).map((content) => ({ userName: "SERVER", content }))),
         }
         case "new_message": return {
             ...state,
@@ -132,7 +174,7 @@ function reducer(state: State, action: Action): State {
             if (state.plannedPlay === null) {
                 return {
                     ...state,
-                    plannedPlay: { tag: "Special", ...action }
+                    plannedPlay: { tag: "Special", card: action.card, color: action.color }
                 }
             }
             return state
@@ -140,13 +182,20 @@ function reducer(state: State, action: Action): State {
     }
 }
 
-export default function useGame(lobbyId: string) {
+export default function useGame(lobbyId: string, password: string | null) {
     const [state, dispatch] = useReducer(reducer, {
         plannedPlay: null,
         messages: [],
-        ownCards: [], topCard: null, turnIndex: 0, selfIndex: 0, users: [], direction: "Clockwise", cardsPlayed: 0, lastPlayedCards: []
+        rules: null,
+        ownCards: [], topCard: null, turnIndex: 0, selfIndex: 0, users: [], direction: "Clockwise", cardsPlayed: 0, lastPlayedCards: [], awaitingSwapTarget: false, roundOver: false, events: []
     })
-    const { sendJsonMessage, lastJsonMessage } = useWebSocket(`ws://${window.location.host}/ws/${lobbyId}`, { onClose: (event) => { console.log(event) } });
+    const { sendJsonMessage, lastJsonMessage, readyState } = useWebSocket(socketUrl(`/lobbies/join/${lobbyId}`), { onClose: (event) => { console.log(event) } });
+
+    useEffect(() => {
+        if (readyState === ReadyState.OPEN) {
+            sendJsonMessage<Request>({ tag: "Hello", fields: { protocol_version: PROTOCOL_VERSION, client_name: "web", password } })
+        }
+    }, [readyState, sendJsonMessage, password]);
 
     useEffect(() => {
         if (lastJsonMessage !== null) {
@@ -156,6 +205,13 @@ export default function useGame(lobbyId: string) {
                 dispatch({ type: "new_game_state", state: data.fields })
             } else if (data.tag === "ChatMessage") {
                 dispatch({ type: "new_message", message: data.fields })
+            } else if (data.tag === "Welcome") {
+                dispatch({ type: "welcome", rules: data.fields.rules })
+            } else if (data.tag === "Error") {
+                console.error(data.fields)
+            } else if (data.tag === "ServerShutdown") {
+                const content = `The server is shutting down in ${data.fields.in_seconds} seconds.`
+                dispatch({ type: "new_message", message: { userName: "SERVER", content } })
             }
         }
 
@@ -169,7 +225,12 @@ export default function useGame(lobbyId: string) {
                     break
                 }
                 case "Special": {
-                    sendJsonMessage<Request>({ tag: "PlaySpecialCard", fields: [state.plannedPlay.card, state.plannedPlay.color] });
+                    const { card, color, target } = state.plannedPlay;
+                    if (target !== undefined) {
+                        sendJsonMessage<Request>({ tag: "PlayTargetedCard", fields: { card, color, target } });
+                    } else {
+                        sendJsonMessage<Request>({ tag: "PlaySpecialCard", fields: [card, color] });
+                    }
                     break
                 }
             }
@@ -187,7 +248,19 @@ export default function useGame(lobbyId: string) {
         sendJsonMessage<Request>({ tag: "TakeCard" })
     }, [sendJsonMessage])
 
-    return { state, dispatch, playCards, takeCard, sendChatMessage }
+    const jumpIn = useCallback((card: number) => {
+        sendJsonMessage<Request>({ tag: "JumpIn", fields: { card, cards_played: state.cardsPlayed } })
+    }, [sendJsonMessage, state.cardsPlayed])
+
+    const chooseSwapTarget = useCallback((target: number) => {
+        sendJsonMessage<Request>({ tag: "ChooseSwapTarget", fields: target })
+    }, [sendJsonMessage])
+
+    const giveToPartner = useCallback((card: number) => {
+        sendJsonMessage<Request>({ tag: "GiveToPartner", fields: card })
+    }, [sendJsonMessage])
+
+    return { state, dispatch, playCards, takeCard, sendChatMessage, chooseSwapTarget, jumpIn, giveToPartner }
 }
 
 
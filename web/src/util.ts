@@ -15,3 +15,9 @@ export function randInt(end: number): number {
 export function last<T>(ls: T[]): T {
     return ls[ls.length - 1]
 }
+
+/** WebSocket URL for an API path, on the same host the page was loaded from */
+export function socketUrl(path: string): string {
+    const protocol = window.location.protocol === "https:" ? "wss" : "ws"
+    return `${protocol}://${window.location.host}/api${path}`
+}
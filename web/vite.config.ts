@@ -8,15 +8,9 @@ export default defineConfig({
     proxy: {
       '/api': {
         target: 'http://localhost:8080',
-        changeOrigin: true,
-        rewrite: (path) => path.replace(/^\/api/, ''),
-      },
-      '/ws': {
-        target: 'ws://localhost:8080/lobbies/join',
         ws: true,
         changeOrigin: true,
-        rewrite: path => path.replace(/^\/ws/, ''),
-      }
+      },
     },
   },
 })